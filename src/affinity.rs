@@ -0,0 +1,220 @@
+use std::collections::BTreeSet;
+use std::fmt::{Display, Formatter};
+use std::process::Command;
+use std::str::FromStr;
+
+use lazy_static::lazy_static;
+
+const AFFINITY_POLICIES: [&str; 3] = ["none", "compact", "scatter"];
+
+/// The largest number of CPUs that the platform can pin a benchmark to
+const MAX_CPUS: usize = 1024;
+
+/// Which CPUs the benchmarks are pinned to, by the number of threads they run with
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum Affinity {
+    /// The benchmarks run wherever the scheduler puts them
+    #[default]
+    None,
+    /// One CPU per thread, filling every hyperthread of a core before going to the next core
+    Compact,
+    /// One CPU per thread, using one hyperthread of every core before using the second one of any
+    Scatter,
+    /// Every benchmark is pinned to these CPUs, whatever its number of threads
+    Cpus(Vec<usize>)
+}
+
+/// A logical CPU, and the physical core it is a hyperthread of
+#[derive(Debug, Clone, Copy)]
+struct Cpu {
+    id: usize,
+    package: usize,
+    core: usize
+}
+
+lazy_static! {
+    /// The CPUs that the platform itself may run on, which the benchmarks are placed on
+    static ref TOPOLOGY: Vec<Cpu> = detect_topology();
+}
+
+/// The CPUs that this process may run on
+#[cfg(target_os = "linux")]
+fn allowed_cpus() -> Vec<usize> {
+    // SAFETY: cpu_set_t is plain data, and sched_getaffinity writes at most its size
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        if libc::sched_getaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &mut set) == 0 {
+            return (0..MAX_CPUS).filter(|cpu| libc::CPU_ISSET(*cpu, &set)).collect();
+        }
+    }
+    (0..std::thread::available_parallelism().map_or(1, |n| n.get())).collect()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn allowed_cpus() -> Vec<usize> {
+    (0..std::thread::available_parallelism().map_or(1, |n| n.get())).collect()
+}
+
+/// Finds the core of every allowed CPU. Without the topology in sysfs, every CPU is its own core
+fn detect_topology() -> Vec<Cpu> {
+    allowed_cpus().into_iter()
+        .map(|id| {
+            let read = |file: &str| -> Option<usize> {
+                std::fs::read_to_string(format!("/sys/devices/system/cpu/cpu{}/topology/{}", id, file))
+                    .ok()
+                    .and_then(|value| value.trim().parse().ok())
+            };
+            Cpu {
+                id,
+                package: read("physical_package_id").unwrap_or(0),
+                core: read("core_id").unwrap_or(id)
+            }
+        })
+        .collect()
+}
+
+/// The number of logical CPUs that the platform may run on, and of the physical cores they are on
+pub fn allowed_cpu_counts() -> (usize, usize) {
+    let cores: BTreeSet<(usize, usize)> = TOPOLOGY.iter().map(|cpu| (cpu.package, cpu.core)).collect();
+    (TOPOLOGY.len(), cores.len())
+}
+
+/// Parses a CPU list, such as `0-3,8,10`
+fn parse_cpu_list(list: &str) -> Result<Vec<usize>, String> {
+    let parse = |cpu: &str| -> Result<usize, String> {
+        match cpu.trim().parse() {
+            Ok(cpu) if cpu < MAX_CPUS => Ok(cpu),
+            Ok(cpu) => Err(format!("CPU {} is out of range, as there can be at most {} CPUs", cpu, MAX_CPUS)),
+            Err(_) => Err(format!("{} is not a CPU number", cpu))
+        }
+    };
+    let mut cpus = vec![];
+    for part in list.split(',') {
+        match part.split_once('-') {
+            Some((first, last)) => {
+                let (first, last) = (parse(first)?, parse(last)?);
+                if first > last {
+                    return Err(format!("{} is not a CPU range, as it is backwards", part));
+                }
+                cpus.extend(first..=last);
+            },
+            None => cpus.push(parse(part)?)
+        }
+    }
+    cpus.sort_unstable();
+    cpus.dedup();
+    Ok(cpus)
+}
+
+impl FromStr for Affinity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Affinity::None),
+            "compact" => Ok(Affinity::Compact),
+            "scatter" => Ok(Affinity::Scatter),
+            list => parse_cpu_list(list)
+                .map(Affinity::Cpus)
+                .map_err(|e| format!("{} is not an affinity policy, expected one of {:?} or a CPU list: {}", list, AFFINITY_POLICIES, e))
+        }
+    }
+}
+
+impl Display for Affinity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Affinity::None => write!(f, "none"),
+            Affinity::Compact => write!(f, "compact"),
+            Affinity::Scatter => write!(f, "scatter"),
+            Affinity::Cpus(cpus) => {
+                let cpus: Vec<String> = cpus.iter().map(|cpu| cpu.to_string()).collect();
+                write!(f, "{}", cpus.join(","))
+            }
+        }
+    }
+}
+
+impl Affinity {
+
+    /// Checks that the benchmarks can be pinned to the CPUs of the policy
+    pub fn validate(&self) -> Result<(), String> {
+        if *self == Affinity::None {
+            return Ok(());
+        }
+        if !cfg!(target_os = "linux") {
+            return Err(String::from("CPU affinity policies are only supported on Linux"));
+        }
+        if let Affinity::Cpus(cpus) = self {
+            let unavailable: Vec<String> = cpus.iter()
+                .filter(|cpu| !TOPOLOGY.iter().any(|c| c.id == **cpu))
+                .map(|cpu| cpu.to_string())
+                .collect();
+            if !unavailable.is_empty() {
+                return Err(format!("CPUs {} don't exist, or the platform is not allowed to run on them", unavailable.join(",")));
+            }
+        }
+        Ok(())
+    }
+
+    /// The number of CPUs that benchmarks may be placed on, which is the most threads they can run in parallel
+    pub fn available_cpus(&self) -> usize {
+        match self {
+            Affinity::Cpus(cpus) => cpus.len(),
+            _ => allowed_cpu_counts().0
+        }
+    }
+
+    /// The CPUs that a benchmark running with the given number of threads is pinned to, or `None` if it
+    /// isn't pinned. With more threads than CPUs, the benchmark is pinned to every CPU.
+    pub fn cpus(&self, threads: usize) -> Option<Vec<usize>> {
+        let mut order: Vec<Cpu> = TOPOLOGY.clone();
+        match self {
+            Affinity::None => return None,
+            Affinity::Cpus(cpus) => return Some(cpus.clone()),
+            Affinity::Compact => order.sort_by_key(|cpu| (cpu.package, cpu.core, cpu.id)),
+            Affinity::Scatter => {
+                // The hyperthreads of each core are numbered, and the first ones of every core go first
+                order.sort_by_key(|cpu| (cpu.package, cpu.core, cpu.id));
+                let mut sibling = vec![0; order.len()];
+                for i in 1..order.len() {
+                    if (order[i].package, order[i].core) == (order[i - 1].package, order[i - 1].core) {
+                        sibling[i] = sibling[i - 1] + 1;
+                    }
+                }
+                let mut numbered: Vec<(usize, Cpu)> = sibling.into_iter().zip(order).collect();
+                // Alternating between packages spreads the threads over every socket
+                numbered.sort_by_key(|(sibling, cpu)| (*sibling, cpu.core, cpu.package, cpu.id));
+                order = numbered.into_iter().map(|(_, cpu)| cpu).collect();
+            }
+        }
+        let mut cpus: Vec<usize> = order.into_iter().take(threads.max(1)).map(|cpu| cpu.id).collect();
+        cpus.sort_unstable();
+        Some(cpus)
+    }
+}
+
+/// Makes the command pin its process to the given CPUs, before it starts running the benchmark
+#[cfg(target_os = "linux")]
+pub fn pin(command: &mut Command, cpus: &[usize]) {
+    use std::os::unix::process::CommandExt;
+
+    // The set is built here, since the child may not allocate between fork and exec
+    // SAFETY: cpu_set_t is plain data, and every CPU was checked to be below MAX_CPUS, its size
+    let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+    for cpu in cpus {
+        unsafe { libc::CPU_SET(*cpu, &mut set) };
+    }
+    // SAFETY: sched_setaffinity is a system call, which is safe to make between fork and exec
+    unsafe {
+        command.pre_exec(move || {
+            if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn pin(_command: &mut Command, _cpus: &[usize]) {}
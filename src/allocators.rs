@@ -0,0 +1,404 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus};
+
+use serde::Deserialize;
+
+use crate::benchmark::LIBRARY_DIR;
+use crate::fingerprint::{hash_sources, Fingerprint};
+use crate::grapher::{parse_color, SeriesStyle};
+use crate::metadata::Toolchain;
+use crate::DYNAMIC_LIBRARY_EXTENSION;
+
+/// The default location of the allocator registry
+pub const REGISTRY_FILE: &str = "./allocators.toml";
+
+/// The list of allocators that the platform knows about, loaded from the registry file
+#[derive(Debug, Deserialize)]
+pub struct Registry {
+    #[serde(rename = "allocator", default)]
+    allocators: Vec<AllocatorSpec>
+}
+
+/// A single allocator entry of the registry
+#[derive(Debug, Clone, Deserialize)]
+pub struct AllocatorSpec {
+    name: String,
+    source_dir: Option<PathBuf>,
+    library: Option<String>,
+    #[serde(default)]
+    always_build: bool,
+    #[serde(default)]
+    clean: Vec<String>,
+    /// Build outputs that the clean commands leave behind, which are deleted before running them
+    #[serde(default)]
+    artifacts: Vec<String>,
+    /// Whether the allocator reads the APF tuning variables from its environment
+    #[serde(default)]
+    apf: bool,
+    prepare: Option<PrepareSpec>,
+    #[serde(rename = "static")]
+    static_build: Option<BuildSpec>,
+    dynamic: Option<BuildSpec>,
+    /// How the allocator is drawn in graphs
+    style: Option<SeriesStyle>,
+    /// The features of a variant made by `with_features`, replacing the features of the build options
+    #[serde(skip)]
+    features: Option<Vec<String>>,
+    /// The commit that a variant made by `with_source` is pinned to
+    #[serde(skip)]
+    revision: Option<String>
+}
+
+/// Commands that must be run once before the allocator can be built, such as a configure script
+#[derive(Debug, Clone, Deserialize)]
+pub struct PrepareSpec {
+    creates: PathBuf,
+    commands: Vec<String>
+}
+
+/// How to build one kind of library (static or dynamic) for an allocator
+#[derive(Debug, Clone, Deserialize)]
+pub struct BuildSpec {
+    build: Vec<String>,
+    artifact: String,
+    debug_build: Option<Vec<String>>,
+    debug_artifact: Option<String>
+}
+
+/// Settings that affect how an allocator is built
+pub struct BuildOptions<'a> {
+    pub dynamic: bool,
+    pub debug: bool,
+    pub features: &'a [&'a str]
+}
+
+#[derive(Debug)]
+pub enum AllocatorError {
+    IO(std::io::Error),
+    Parse(toml::de::Error),
+    Invalid(String),
+    /// A revision of the sources could not be checked out
+    Revision(String),
+    ExitStatus(String, ExitStatus)
+}
+
+impl From<std::io::Error> for AllocatorError {
+    fn from(e: std::io::Error) -> Self {
+        AllocatorError::IO(e)
+    }
+}
+
+impl From<toml::de::Error> for AllocatorError {
+    fn from(e: toml::de::Error) -> Self {
+        AllocatorError::Parse(e)
+    }
+}
+
+impl Display for AllocatorError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AllocatorError::IO(e) => write!(f, "{}", e),
+            AllocatorError::Parse(e) => write!(f, "Invalid allocator registry: {}", e),
+            AllocatorError::Invalid(msg) => write!(f, "Invalid allocator registry: {}", msg),
+            AllocatorError::Revision(msg) => write!(f, "{}", msg),
+            AllocatorError::ExitStatus(command, status) => write!(f, "`{}` failed with {}", command, status)
+        }
+    }
+}
+
+impl Registry {
+
+    /// Loads and validates the registry at the given path
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, AllocatorError> {
+        let text = std::fs::read_to_string(path)?;
+        let registry: Registry = toml::from_str(&text)?;
+
+        let mut seen: Vec<&str> = vec![];
+        for allocator in &registry.allocators {
+            if seen.contains(&allocator.name.as_str()) {
+                return Err(AllocatorError::Invalid(format!("{} is declared more than once", allocator.name)));
+            }
+            seen.push(&allocator.name);
+
+            if allocator.source_dir.is_some() && allocator.static_build.is_none() && allocator.dynamic.is_none() {
+                return Err(AllocatorError::Invalid(format!("{} has a source_dir but no static or dynamic build", allocator.name)));
+            }
+
+            if let Some(color) = allocator.style.as_ref().and_then(|s| s.color.as_ref()) {
+                parse_color(color).map_err(|e| AllocatorError::Invalid(format!("{}: {}", allocator.name, e)))?;
+            }
+        }
+
+        Ok(registry)
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.allocators.iter().map(|a| a.name.as_str()).collect()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&AllocatorSpec> {
+        self.allocators.iter().find(|a| a.name == name)
+    }
+
+    pub fn allocators(&self) -> &[AllocatorSpec] {
+        &self.allocators
+    }
+
+    /// The graph styles declared for the allocators, by allocator name
+    pub fn styles(&self) -> BTreeMap<String, SeriesStyle> {
+        self.allocators.iter()
+            .filter_map(|a| a.style.clone().map(|style| (a.name.clone(), style)))
+            .collect()
+    }
+}
+
+/// Fills in the placeholders of a command line or artifact from the registry
+fn fill_placeholders(line: &str, features: &[String], build_dir: &str) -> String {
+    let features = if features.is_empty() {
+        String::new()
+    } else {
+        format!("--features {}", features.join(","))
+    };
+    line
+        .replace("{dylib_ext}", DYNAMIC_LIBRARY_EXTENSION)
+        .replace("{features}", &features)
+        .replace("{build_dir}", build_dir)
+}
+
+/// Splits a command line from the registry into a runnable command, filling in the placeholders
+fn parse_command(line: &str, features: &[String], build_dir: &str) -> Option<Command> {
+    let line = fill_placeholders(line, features, build_dir);
+    let mut parts = line.split_whitespace();
+    let mut command = Command::new(parts.next()?);
+    command.args(parts);
+    Some(command)
+}
+
+impl AllocatorSpec {
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn source_dir(&self) -> Option<&Path> {
+        self.source_dir.as_deref()
+    }
+
+    /// The base name of the library this allocator produces, or `None` if nothing needs to be linked
+    pub fn lib_name(&self) -> Option<&str> {
+        self.source_dir.as_ref()?;
+        Some(self.library.as_deref().unwrap_or(&self.name))
+    }
+
+    /// The path of the library inside the library dir
+    pub fn lib_file(&self, dynamic: bool) -> Option<PathBuf> {
+        let extension = if dynamic { DYNAMIC_LIBRARY_EXTENSION } else { ".a" };
+        let mut path = PathBuf::from(LIBRARY_DIR);
+        path.push(format!("lib{}{}", self.lib_name()?, extension));
+        Some(path)
+    }
+
+    pub fn apf(&self) -> bool {
+        self.apf
+    }
+
+    /// Whether the registry declares how to build the dynamic or the static library of the allocator
+    pub fn can_build(&self, dynamic: bool) -> bool {
+        if dynamic { self.dynamic.is_some() } else { self.static_build.is_some() }
+    }
+
+    /// Every prepare, build and clean command of the allocator, without the placeholders filled in
+    pub fn commands(&self) -> Vec<&str> {
+        self.prepare.iter()
+            .flat_map(|prepare| prepare.commands.iter())
+            .chain([&self.static_build, &self.dynamic].iter()
+                .filter_map(|spec| spec.as_ref())
+                .flat_map(|spec| spec.build.iter().chain(spec.debug_build.iter().flatten())))
+            .chain(self.clean.iter())
+            .map(|command| command.as_str())
+            .collect()
+    }
+
+    /// Whether the build commands of the allocator take the `{features}` placeholder
+    pub fn has_features(&self) -> bool {
+        [&self.static_build, &self.dynamic].iter()
+            .filter_map(|spec| spec.as_ref())
+            .flat_map(|spec| spec.build.iter().chain(spec.debug_build.iter().flatten()))
+            .any(|command| command.contains("{features}"))
+    }
+
+    /// A variant of the allocator built with the given features, which is named `name[feature,...]`
+    /// and has its own library and build directory. An empty feature set is the allocator itself.
+    pub fn with_features(&self, features: &[String]) -> AllocatorSpec {
+        let mut variant = self.clone();
+        if !features.is_empty() {
+            variant.name = format!("{}[{}]", self.name, features.join(","));
+            variant.library = Some(format!("{}-{}", self.library.as_deref().unwrap_or(&self.name), features.join("-")));
+        }
+        variant.features = Some(features.to_vec());
+        variant
+    }
+
+    /// A variant of the allocator built from other sources, such as a worktree of one of its commits
+    pub fn with_source(&self, name: &str, source_dir: PathBuf, library: &str, revision: Option<String>) -> AllocatorSpec {
+        let mut variant = self.clone();
+        variant.name = name.to_string();
+        variant.source_dir = Some(source_dir);
+        variant.library = Some(library.to_string());
+        variant.revision = revision;
+        variant
+    }
+
+    /// The directory that the `{build_dir}` placeholder refers to
+    fn build_dir(&self) -> String {
+        match &self.features {
+            Some(features) if !features.is_empty() => format!("target/features-{}", features.join("-")),
+            _ => String::from("target")
+        }
+    }
+
+    /// The features the allocator is built with, which are those of the variant or of the build options
+    fn features(&self, options: &BuildOptions) -> Vec<String> {
+        match &self.features {
+            Some(features) => features.clone(),
+            None => options.features.iter().map(|f| f.to_string()).collect()
+        }
+    }
+
+    /// How to build the kind of library the options ask for, and the build commands and artifact to use
+    fn build_spec(&self, options: &BuildOptions) -> Result<(&Vec<String>, &String), AllocatorError> {
+        let spec = if options.dynamic { &self.dynamic } else { &self.static_build };
+        let spec = spec.as_ref().ok_or_else(|| AllocatorError::Invalid(
+            format!("{} can not be built as a {} library", self.name, if options.dynamic { "dynamic" } else { "static" })
+        ))?;
+        Ok(if options.debug {
+            (spec.debug_build.as_ref().unwrap_or(&spec.build), spec.debug_artifact.as_ref().unwrap_or(&spec.artifact))
+        } else {
+            (&spec.build, &spec.artifact)
+        })
+    }
+
+    /// Everything the library built with these options depends on
+    pub fn fingerprint(&self, options: &BuildOptions, toolchain: &Toolchain) -> Result<Fingerprint, AllocatorError> {
+        let source_dir = self.source_dir.as_ref()
+            .ok_or_else(|| AllocatorError::Invalid(format!("{} has no sources to build", self.name)))?;
+        let features = self.features(options);
+        let build_dir = self.build_dir();
+        let (build, artifact) = self.build_spec(options)?;
+
+        // A pinned revision is identified by its commit, which saves hashing its sources
+        let sources = match &self.revision {
+            Some(commit) => format!("commit {}", commit),
+            None => hash_sources(source_dir)?
+        };
+        let commands = self.prepare.iter()
+            .flat_map(|prepare| prepare.commands.iter())
+            .chain(build.iter())
+            .chain(std::iter::once(artifact))
+            .map(|line| fill_placeholders(line, &features, &build_dir))
+            .collect();
+
+        Ok(Fingerprint {
+            sources,
+            dynamic: options.dynamic,
+            debug: options.debug,
+            features,
+            commands,
+            toolchain: toolchain.clone()
+        })
+    }
+
+    /// Builds the allocator unless its library was already built with the same fingerprint, or the
+    /// allocator is always built. Returns whether it was built.
+    pub fn build_if_changed(&self, options: &BuildOptions, toolchain: &Toolchain) -> Result<bool, AllocatorError> {
+        let library = match self.lib_file(options.dynamic) {
+            Some(library) => library,
+            None => return Ok(false)
+        };
+        let fingerprint = self.fingerprint(options, toolchain)?;
+        if !self.always_build && fingerprint.matches(&library) {
+            return Ok(false);
+        }
+        self.build(options)?;
+        fingerprint.save(&library)?;
+        Ok(true)
+    }
+
+    fn run_commands(&self, commands: &[String], options: &BuildOptions) -> Result<(), AllocatorError> {
+        let dir = match &self.source_dir {
+            Some(dir) => dir,
+            None => return Ok(())
+        };
+        let features = self.features(options);
+        for line in commands {
+            let mut command = match parse_command(line, &features, &self.build_dir()) {
+                Some(command) => command,
+                None => continue
+            };
+            let status = command.current_dir(dir).status()?;
+            if !status.success() {
+                return Err(AllocatorError::ExitStatus(line.clone(), status));
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs the prepare commands if their output doesn't exist yet
+    pub fn prepare(&self, options: &BuildOptions) -> Result<(), AllocatorError> {
+        if let (Some(dir), Some(prepare)) = (&self.source_dir, &self.prepare) {
+            if !dir.join(&prepare.creates).exists() {
+                self.run_commands(&prepare.commands, options)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Builds the allocator and copies the produced library into the library dir
+    pub fn build(&self, options: &BuildOptions) -> Result<(), AllocatorError> {
+        let (source_dir, dest) = match (&self.source_dir, self.lib_file(options.dynamic)) {
+            (Some(dir), Some(dest)) => (dir, dest),
+            _ => return Ok(())
+        };
+        let (commands, artifact) = self.build_spec(options)?;
+
+        self.prepare(options)?;
+        self.run_commands(commands, options)?;
+
+        let artifact = source_dir.join(fill_placeholders(artifact, &self.features(options), &self.build_dir()));
+        std::fs::create_dir_all(LIBRARY_DIR)?;
+        std::fs::copy(&artifact, &dest)?;
+        Ok(())
+    }
+
+    /// Whether the allocator has clean commands and sources to run them in
+    pub fn has_clean_commands(&self) -> bool {
+        !self.clean.is_empty() && self.source_dir.as_ref().is_some_and(|dir| dir.exists())
+    }
+
+    /// The build outputs declared as `artifacts`, inside of the sources of the allocator
+    pub fn artifacts(&self) -> Vec<PathBuf> {
+        let source_dir = match &self.source_dir {
+            Some(dir) => dir,
+            None => return vec![]
+        };
+        let features = self.features.clone().unwrap_or_default();
+        self.artifacts.iter()
+            .map(|artifact| source_dir.join(fill_placeholders(artifact, &features, &self.build_dir())))
+            .collect()
+    }
+
+    /// Runs the clean commands of the allocator
+    pub fn clean(&self) -> Result<(), AllocatorError> {
+        let options = BuildOptions {
+            dynamic: false,
+            debug: false,
+            features: &[]
+        };
+        match &self.source_dir {
+            Some(dir) if dir.exists() => self.run_commands(&self.clean, &options),
+            _ => Ok(())
+        }
+    }
+}
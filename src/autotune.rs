@@ -0,0 +1,337 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::path::Path;
+use std::str::FromStr;
+
+use serde::Serialize;
+
+use crate::grapher::{Graph, GraphOptions};
+use crate::stats::Samples;
+
+/// The name of the autotuning report inside of a results directory
+pub const AUTOTUNE_FILE: &str = "autotune.json";
+
+pub const STRATEGIES: [&str; 2] = ["golden", "coarse"];
+
+/// How many evenly spaced values the coarse-to-fine search tries in each round
+const COARSE_POINTS: usize = 5;
+
+/// How the values of a variable are searched
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Strategy {
+    /// Golden-section search, which assumes that the throughput has a single peak in the range
+    Golden,
+    /// Tries evenly spaced values, then narrows the range around the best one
+    Coarse
+}
+
+impl FromStr for Strategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "golden" => Ok(Strategy::Golden),
+            "coarse" => Ok(Strategy::Coarse),
+            other => Err(format!("{} is not a search strategy, expected one of {:?}", other, STRATEGIES))
+        }
+    }
+}
+
+impl Display for Strategy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Strategy::Golden => write!(f, "golden"),
+            Strategy::Coarse => write!(f, "coarse")
+        }
+    }
+}
+
+/// The inclusive range of values that a variable is searched in
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchRange {
+    pub var: String,
+    pub min: usize,
+    pub max: usize
+}
+
+impl SearchRange {
+
+    /// Parses a range given as `min:max`
+    pub fn parse(var: &str, spec: &str) -> Result<Self, String> {
+        let parts: Vec<&str> = spec.split(':').collect();
+        let (min, max) = match parts.as_slice() {
+            [min, max] => (
+                min.trim().parse::<usize>().map_err(|_| format!("{} is not a valid value of {}", min, var))?,
+                max.trim().parse::<usize>().map_err(|_| format!("{} is not a valid value of {}", max, var))?
+            ),
+            _ => return Err(format!("{} is not a range of {}, expected min:max", spec, var))
+        };
+        if min >= max {
+            return Err(format!("the range of {} must have a minimum below its maximum", var));
+        }
+        Ok(Self { var: var.to_string(), min, max })
+    }
+
+    fn midpoint(&self) -> usize {
+        self.min + (self.max - self.min) / 2
+    }
+}
+
+/// What to search and how
+pub struct AutotuneOptions {
+    pub strategy: Strategy,
+    /// The maximum number of settings that are measured
+    pub budget: usize,
+    /// The ranges of the variables, which are searched one after the other
+    pub ranges: Vec<SearchRange>
+}
+
+/// One measured setting of the variables
+#[derive(Debug, Serialize)]
+pub struct Evaluation {
+    pub env: BTreeMap<String, String>,
+    /// The aggregated throughput of the trials, or `None` if the benchmark crashed
+    pub throughput: Option<f64>,
+    #[serde(skip)]
+    samples: Option<Samples>
+}
+
+/// The search of one variable, with the other variables held at fixed values
+#[derive(Debug, Serialize)]
+pub struct Stage {
+    pub var: String,
+    pub fixed: BTreeMap<String, String>,
+    /// The indices of the evaluations made in this stage
+    pub evaluations: Vec<usize>
+}
+
+/// The outcome of an autotuning run
+#[derive(Debug, Serialize)]
+pub struct Autotune {
+    pub benchmark: String,
+    pub allocator: String,
+    pub threads: usize,
+    pub strategy: Strategy,
+    pub budget: usize,
+    pub ranges: Vec<SearchRange>,
+    pub best: Option<BTreeMap<String, String>>,
+    pub best_throughput: Option<f64>,
+    pub stages: Vec<Stage>,
+    pub evaluations: Vec<Evaluation>
+}
+
+/// Measures settings of the variables, remembering what has already been measured and enforcing the budget
+struct Evaluator<'a, F> {
+    evaluate: F,
+    budget: usize,
+    evaluations: &'a mut Vec<Evaluation>,
+    stage: &'a mut Stage
+}
+
+impl<F: FnMut(&BTreeMap<String, String>) -> Option<Samples>> Evaluator<'_, F> {
+
+    /// The throughput with the variable of the stage set to `value`, where a crash counts as no throughput.
+    /// Returns `None` once the budget is used up.
+    fn throughput(&mut self, value: usize) -> Option<f64> {
+        let mut env = self.stage.fixed.clone();
+        env.insert(self.stage.var.clone(), value.to_string());
+
+        if let Some(index) = self.evaluations.iter().position(|e| e.env == env) {
+            if !self.stage.evaluations.contains(&index) {
+                self.stage.evaluations.push(index);
+            }
+            return Some(self.evaluations[index].throughput.unwrap_or(0.0));
+        }
+        if self.evaluations.len() >= self.budget {
+            return None;
+        }
+
+        let samples = (self.evaluate)(&env);
+        let throughput = samples.as_ref().map(Samples::value);
+        self.stage.evaluations.push(self.evaluations.len());
+        self.evaluations.push(Evaluation { env, throughput, samples });
+        Some(throughput.unwrap_or(0.0))
+    }
+
+    /// The value of the variable with the highest throughput measured so far in this stage
+    fn best(&self) -> Option<usize> {
+        self.stage.evaluations.iter()
+            .map(|&i| &self.evaluations[i])
+            .filter(|e| e.throughput.is_some())
+            .max_by(|a, b| a.throughput.partial_cmp(&b.throughput).unwrap())
+            .map(|e| e.env[&self.stage.var].parse().unwrap())
+    }
+
+    fn golden(&mut self, range: &SearchRange) -> Option<()> {
+        let ratio = (5f64.sqrt() - 1.0) / 2.0;
+        let (mut low, mut high) = (range.min as f64, range.max as f64);
+        // The interior points are carried over between iterations, so each one measures a single new value
+        let mut left = high - ratio * (high - low);
+        let mut right = low + ratio * (high - low);
+        while right.round() > left.round() {
+            if self.throughput(left.round() as usize)? >= self.throughput(right.round() as usize)? {
+                high = right;
+                right = left;
+                left = high - ratio * (high - low);
+            } else {
+                low = left;
+                left = right;
+                right = low + ratio * (high - low);
+            }
+        }
+        for value in low.round() as usize..=high.round() as usize {
+            self.throughput(value)?;
+        }
+        Some(())
+    }
+
+    fn coarse(&mut self, range: &SearchRange) -> Option<()> {
+        let (mut low, mut high) = (range.min, range.max);
+        loop {
+            let width = (high - low) as f64;
+            let step = (width / (COARSE_POINTS - 1) as f64).ceil() as usize;
+            for i in 0..COARSE_POINTS {
+                self.throughput(low + (width * i as f64 / (COARSE_POINTS - 1) as f64).round() as usize)?;
+            }
+            if step <= 1 {
+                return Some(());
+            }
+            let best = self.best().unwrap_or(low).clamp(low, high);
+            low = best.saturating_sub(step).max(range.min);
+            high = (best + step).min(range.max);
+        }
+    }
+}
+
+/// Searches the variables one after the other for the setting with the best throughput
+///
+/// Each variable is searched with the variables before it at their best values and the ones after
+/// it at the midpoints of their ranges. The budget is split evenly between the variables.
+pub fn autotune<F>(
+    benchmark: &str,
+    allocator: &str,
+    threads: usize,
+    options: AutotuneOptions,
+    mut evaluate: F
+) -> Autotune
+    where F: FnMut(&BTreeMap<String, String>) -> Option<Samples> {
+    let mut evaluations = vec![];
+    let mut stages = vec![];
+    let mut fixed: BTreeMap<String, String> = options.ranges.iter()
+        .map(|range| (range.var.clone(), range.midpoint().to_string()))
+        .collect();
+
+    for (i, range) in options.ranges.iter().enumerate() {
+        fixed.remove(&range.var);
+        let mut stage = Stage {
+            var: range.var.clone(),
+            fixed: fixed.clone(),
+            evaluations: vec![]
+        };
+        // Later stages may use what earlier stages left over
+        let budget = evaluations.len() + (options.budget - evaluations.len()) / (options.ranges.len() - i);
+        let mut evaluator = Evaluator {
+            evaluate: &mut evaluate,
+            budget,
+            evaluations: &mut evaluations,
+            stage: &mut stage
+        };
+        let _ = match options.strategy {
+            Strategy::Golden => evaluator.golden(range),
+            Strategy::Coarse => evaluator.coarse(range)
+        };
+        let best = evaluator.best().unwrap_or_else(|| range.midpoint());
+        fixed.insert(range.var.clone(), best.to_string());
+        stages.push(stage);
+    }
+
+    let best = evaluations.iter()
+        .filter(|e| e.throughput.is_some())
+        .max_by(|a, b| a.throughput.partial_cmp(&b.throughput).unwrap());
+
+    Autotune {
+        benchmark: benchmark.to_string(),
+        allocator: allocator.to_string(),
+        threads,
+        strategy: options.strategy,
+        budget: options.budget,
+        ranges: options.ranges,
+        best: best.map(|e| e.env.clone()),
+        best_throughput: best.and_then(|e| e.throughput),
+        stages,
+        evaluations
+    }
+}
+
+/// Shows the variables as `name=value,name=value`
+fn env_label(env: &BTreeMap<String, String>) -> String {
+    let vars: Vec<String> = env.iter().map(|(name, value)| format!("{}={}", name, value)).collect();
+    vars.join(",")
+}
+
+impl Autotune {
+
+    /// Prints every measured setting, from best to worst, followed by the best setting
+    pub fn report(&self) {
+        let mut evaluations: Vec<&Evaluation> = self.evaluations.iter().collect();
+        evaluations.sort_by(|a, b| b.throughput.partial_cmp(&a.throughput).unwrap());
+
+        println!("{:<40} {:>12}", "setting", "throughput");
+        for evaluation in evaluations {
+            match evaluation.throughput {
+                Some(throughput) => println!("{:<40} {:>12.3}", env_label(&evaluation.env), throughput),
+                None => println!("{:<40} {:>12}", env_label(&evaluation.env), "crashed")
+            }
+        }
+
+        match (&self.best, self.best_throughput) {
+            (Some(best), Some(throughput)) => println!(
+                "Best setting for {} with {} threads: {} ({:.3})",
+                self.benchmark, self.threads, env_label(best), throughput
+            ),
+            _ => println!("Every setting crashed")
+        }
+    }
+
+    /// Writes the report into the given results directory
+    pub fn save<P: AsRef<Path>>(&self, dir: P) -> Result<(), std::io::Error> {
+        let file = File::create(dir.as_ref().join(AUTOTUNE_FILE))?;
+        serde_json::to_writer_pretty(file, self).map_err(std::io::Error::other)
+    }
+
+    /// Draws the throughput against the value of the variable, for every stage of the search
+    pub fn make_graphs(&self, dir: &Path, options: &GraphOptions) -> Result<(), Box<dyn Error>> {
+        for stage in &self.stages {
+            let mut curve: Vec<(usize, Samples)> = stage.evaluations.iter()
+                .map(|&i| &self.evaluations[i])
+                .filter_map(|e| Some((e.env[&stage.var].parse().ok()?, e.samples.clone()?)))
+                .collect();
+            if curve.is_empty() {
+                continue;
+            }
+            curve.sort_by_key(|(value, _)| *value);
+
+            let series = if stage.fixed.is_empty() {
+                self.allocator.clone()
+            } else {
+                format!("{}[{}]", self.allocator, env_label(&stage.fixed))
+            };
+            let mut results = BTreeMap::new();
+            results.insert(series, curve);
+
+            let options = GraphOptions {
+                title: format!("{} Throughput vs {} ({} threads)", self.benchmark, stage.var, self.threads),
+                x_desc: stage.var.clone(),
+                // The x axis is the variable, not the number of threads
+                thread_markers: vec![],
+                ..options.clone()
+            };
+            let name = format!("autotune-{}-{}", self.benchmark, stage.var);
+            Graph::new(name, results, &options).make_graph(dir)?;
+        }
+        Ok(())
+    }
+}
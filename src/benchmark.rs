@@ -1,27 +1,162 @@
+use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
 use std::ffi::OsString;
 use std::io::{Error};
 use std::process::{Command, ExitStatus};
 use std::ops::Deref;
-use std::fmt::Debug;
+use std::fmt::{Debug, Display, Formatter};
 use crate::{BINARY_DIR, is_debug, DYNAMIC_MODE};
 use std::sync::atomic::Ordering;
 use std::iter::FromIterator;
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use crate::affinity::Affinity;
+use crate::params::{cartesian_product, value_to_string, Combination, Sweep};
+use crate::stats::OutlierPolicy;
 
 pub struct Benchmark {
     src_dir: PathBuf,
-    benchmark_name: OsString
+    benchmark_name: OsString,
+    manifest: Manifest
+}
+
+/// The contents of the `benchmark.toml` file found in every benchmark directory
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    /// The arguments passed to the benchmark binary, with `{name}` placeholders for parameters
+    args: String,
+    /// The default value of each parameter in `args`
+    #[serde(default)]
+    params: BTreeMap<String, toml::Value>,
+    /// The parameters that are swept over by default
+    #[serde(default)]
+    sweep: BTreeMap<String, Sweep>,
+    /// The text in `args` that is replaced by the number of threads
+    #[serde(default = "Manifest::default_thread_placeholder")]
+    thread_placeholder: String,
+    /// The make target that creates the object files
+    #[serde(default = "Manifest::default_build_target")]
+    build_target: String,
+    /// The make target that creates the object files with debug symbols
+    #[serde(default = "Manifest::default_debug_build_target")]
+    debug_build_target: String,
+    /// The object files created by the build targets, linked together into the benchmark binary
+    objects: Vec<String>,
+    description: Option<String>,
+    /// How many times each point is run
+    #[serde(default)]
+    trials: TrialSettings
+}
+
+/// How many times a benchmark is run for every point, and how those runs are combined
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TrialSettings {
+    /// The number of measured trials
+    #[serde(default = "TrialSettings::default_count")]
+    pub count: usize,
+    /// The number of runs before the measured trials whose results are discarded
+    #[serde(default)]
+    pub warmup: usize,
+    #[serde(default)]
+    pub outliers: OutlierPolicy,
+    /// How often the memory of every run is sampled, if at all, which is set from the command line
+    #[serde(skip)]
+    pub memory_interval: Option<Duration>,
+    /// Whether perf event counters are opened on every run, which is set from the command line
+    #[serde(skip)]
+    pub counters: bool,
+    /// Which CPUs the runs are pinned to, which is set from the command line
+    #[serde(skip)]
+    pub affinity: Affinity
+}
+
+/// Trial settings given on the command line, which replace those of the benchmark manifests
+#[derive(Debug, Clone, Default)]
+pub struct TrialOverrides {
+    pub count: Option<usize>,
+    pub warmup: Option<usize>,
+    pub outliers: Option<OutlierPolicy>,
+    pub memory_interval: Option<Duration>,
+    pub counters: bool,
+    pub affinity: Affinity
+}
+
+impl TrialSettings {
+    fn default_count() -> usize {
+        3
+    }
+
+    /// These settings, with anything given on the command line in place of the manifest
+    pub fn with_overrides(self, overrides: &TrialOverrides) -> Self {
+        Self {
+            count: overrides.count.unwrap_or(self.count),
+            warmup: overrides.warmup.unwrap_or(self.warmup),
+            outliers: overrides.outliers.unwrap_or(self.outliers),
+            memory_interval: overrides.memory_interval,
+            counters: overrides.counters,
+            affinity: overrides.affinity.clone()
+        }
+    }
+}
+
+impl Default for TrialSettings {
+    fn default() -> Self {
+        Self {
+            count: Self::default_count(),
+            warmup: 0,
+            outliers: OutlierPolicy::default(),
+            memory_interval: None,
+            counters: false,
+            affinity: Affinity::None
+        }
+    }
+}
+
+impl Display for TrialSettings {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Trials: {}, Warmup runs: {}, Outlier policy: {}", self.count, self.warmup, self.outliers)?;
+        if let Some(interval) = self.memory_interval {
+            write!(f, ", Memory sampled every {}ms", interval.as_millis())?;
+        }
+        if self.counters {
+            write!(f, ", Counting perf events")?;
+        }
+        if self.affinity != Affinity::None {
+            write!(f, ", CPU affinity: {}", self.affinity)?;
+        }
+        Ok(())
+    }
+}
+
+impl Manifest {
+    fn default_thread_placeholder() -> String {
+        String::from("{threads}")
+    }
+
+    fn default_build_target() -> String {
+        String::from("build")
+    }
+
+    fn default_debug_build_target() -> String {
+        String::from("build_debug")
+    }
 }
 
-const OBJECT_DIR: &str = "./benchmarks/objects";
+pub const OBJECT_DIR: &str = "./benchmarks/objects";
 pub const LIBRARY_DIR: &str = "./allocators/target";
 pub const BENCHMARK_DIR: &str = "./benchmarks/sources";
+pub const MANIFEST_FILE: &str = "benchmark.toml";
 const COMMON_DIR: &str = "common";
 
 #[derive(Debug)]
 pub enum BenchmarkError {
     IO(std::io::Error),
-    ExitStatus(ExitStatus)
+    ExitStatus(ExitStatus),
+    Manifest(PathBuf, String)
 }
 
 impl From<std::io::Error> for BenchmarkError{
@@ -30,19 +165,128 @@ impl From<std::io::Error> for BenchmarkError{
     }
 }
 
-impl Benchmark {
-    pub fn new<P : Deref<Target=Path> + Debug>(path: P) -> Self {
-        if !path.exists() {
-            panic!("{:?} is a not real benchmark", path);
+impl Display for BenchmarkError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BenchmarkError::IO(e) => write!(f, "{}", e),
+            BenchmarkError::ExitStatus(status) => write!(f, "Process exited with {}", status),
+            BenchmarkError::Manifest(path, msg) => write!(f, "Invalid benchmark manifest {:?}: {}", path, msg)
         }
+    }
+}
+
+impl Benchmark {
+    pub fn new<P : Deref<Target=Path> + Debug>(path: P) -> Result<Self, BenchmarkError> {
         if !path.is_dir() {
-            panic!("{:?} must be a directory", path);
+            let kind = if path.exists() { std::io::ErrorKind::InvalidInput } else { std::io::ErrorKind::NotFound };
+            return Err(BenchmarkError::IO(Error::new(kind, format!("{:?} is not a benchmark directory", path))));
         }
         let name = path.file_name().unwrap().to_os_string();
-        Self {
+        let manifest = Self::read_manifest(&path)?;
+        Ok(Self {
             src_dir: path.to_path_buf(),
-            benchmark_name: name
+            benchmark_name: name,
+            manifest
+        })
+    }
+
+    /// Reads and validates the manifest of the benchmark in the given directory
+    fn read_manifest(dir: &Path) -> Result<Manifest, BenchmarkError> {
+        let path = dir.join(MANIFEST_FILE);
+        let invalid = |msg: String| BenchmarkError::Manifest(path.clone(), msg);
+
+        let text = std::fs::read_to_string(&path)
+            .map_err(|e| invalid(format!("could not be read ({})", e)))?;
+        let manifest: Manifest = toml::from_str(&text)
+            .map_err(|e| invalid(e.to_string()))?;
+
+        if manifest.thread_placeholder.is_empty() {
+            return Err(invalid(String::from("thread_placeholder can not be empty")));
+        }
+        if !manifest.args.contains(&manifest.thread_placeholder) {
+            return Err(invalid(format!("args {:?} do not contain the thread placeholder {:?}", manifest.args, manifest.thread_placeholder)));
+        }
+        if manifest.objects.is_empty() {
+            return Err(invalid(String::from("at least one object file must be listed")));
         }
+        if manifest.trials.count == 0 {
+            return Err(invalid(String::from("trials.count must be at least 1")));
+        }
+        for (name, value) in &manifest.params {
+            value_to_string(value).map_err(|e| invalid(format!("param {}: {}", name, e)))?;
+        }
+
+        let without_threads = manifest.args.replace(&manifest.thread_placeholder, "");
+        let mut rest = without_threads.as_str();
+        while let Some(start) = rest.find('{') {
+            let end = rest[start..].find('}')
+                .ok_or_else(|| invalid(format!("args {:?} contain an unclosed placeholder", manifest.args)))?;
+            let name = &rest[start + 1..start + end];
+            if !manifest.params.contains_key(name) {
+                return Err(invalid(format!("args use the parameter {:?}, which is not declared in [params]", name)));
+            }
+            rest = &rest[start + end + 1..];
+        }
+
+        for (name, sweep) in &manifest.sweep {
+            if !manifest.params.contains_key(name) {
+                return Err(invalid(format!("{:?} is swept, but is not declared in [params]", name)));
+            }
+            sweep.values().map_err(|e| invalid(format!("sweep {}: {}", name, e)))?;
+        }
+        Ok(manifest)
+    }
+
+    /// Whether the benchmark has a parameter with the given name
+    pub fn has_param(&self, name: &str) -> bool {
+        self.manifest.params.contains_key(name)
+    }
+
+    /// Every combination of parameters that the benchmark should be run with
+    ///
+    /// Sweeps given in `overrides` replace the sweeps of the manifest. Overrides for parameters this
+    /// benchmark doesn't have are ignored.
+    pub fn get_combinations(&self, overrides: &[(String, Sweep)]) -> Result<Vec<Combination>, BenchmarkError> {
+        let invalid = |msg: String| BenchmarkError::Manifest(self.src_dir.join(MANIFEST_FILE), msg);
+
+        let defaults = self.manifest.params.iter()
+            .map(|(name, value)| Ok((name.clone(), value_to_string(value)?)))
+            .collect::<Result<BTreeMap<_, _>, String>>()
+            .map_err(invalid)?;
+
+        let mut sweeps = BTreeMap::new();
+        for (name, sweep) in &self.manifest.sweep {
+            sweeps.insert(name.clone(), sweep.values().map_err(invalid)?);
+        }
+        for (name, sweep) in overrides {
+            if self.has_param(name) {
+                let values = sweep.values().map_err(|e| invalid(format!("sweep {}: {}", name, e)))?;
+                sweeps.insert(name.clone(), values);
+            }
+        }
+
+        Ok(cartesian_product(&defaults, &sweeps))
+    }
+
+    /// The trial settings of the manifest
+    pub fn get_trial_settings(&self) -> TrialSettings {
+        self.manifest.trials.clone()
+    }
+
+    pub fn get_description(&self) -> Option<&str> {
+        self.manifest.description.as_deref()
+    }
+
+    /// The arguments to run the benchmark binary with for the given parameters and number of threads
+    pub fn get_args(&self, combination: &Combination, thread_count: usize) -> Vec<String> {
+        let mut args = self.manifest.args.replace(&self.manifest.thread_placeholder, &thread_count.to_string());
+        for (name, value) in combination.values() {
+            args = args.replace(&format!("{{{}}}", name), value);
+        }
+        args
+            .split_whitespace()
+            .map(|s| s.to_string())
+            .collect()
     }
 
     /// Creates the directory for which objects are placed
@@ -61,78 +305,45 @@ impl Benchmark {
         }
     }
 
-    fn clean_folder(folder: &Path) {
-        let dir = std::fs::read_dir(folder).expect("Not a folder");
-        for entry in dir {
-            match entry {
-                Ok(entry) => {
-                    let path = entry.path();
-                    if path.is_dir() {
-                        Self::clean_folder(&*path);
-                    } else {
-                        let _ = std::fs::remove_file(path);
-                    }
-                },
-                Err(_) => {},
-            }
-        }
-        std::fs::remove_dir(folder).unwrap();
-    }
-
-    pub fn clean_benchmarks() {
-        let dir = Path::new(BINARY_DIR);
-        if dir.exists() && dir.is_dir() {
-            Self::clean_folder(dir)
-        }
-        let dir = Path::new(OBJECT_DIR);
-        if dir.exists() && dir.is_dir() {
-            Self::clean_folder(dir)
-        }
-    }
-
-    fn get_object_file(&self) -> OsString {
-        OsString::from(format!("{}.o", self.benchmark_name.clone().into_string().unwrap()))
+    /// The directory that this benchmark's object files are moved to
+    fn get_object_dir(&self) -> PathBuf {
+        PathBuf::from_iter(&[Path::new(OBJECT_DIR), Path::new(&self.benchmark_name)])
     }
 
     /// Creates an object file that has not been linked to an allocator yet
     ///
     /// Returns an error if it could not successfully create the object files
-    pub fn create_object_file(&self) -> Result<(), std::io::Error> {
+    pub fn create_object_file(&self) -> Result<(), BenchmarkError> {
         Self::create_objects_dir();
 
         // Runs the make file in the benchmark folder
-        if is_debug() {
-            let result = Command::new("make")
-                .arg("build_debug")
-                .current_dir(&self.src_dir)
-                .status();
-            if result.is_err() || !result.unwrap().success() {
-                panic!("Failed to create object file for {:?}", self.benchmark_name)
-            }
+        let target = if is_debug() {
+            &self.manifest.debug_build_target
         } else {
-            let result = Command::new("make")
-                .arg("build")
-                .current_dir(&self.src_dir)
-                .status();
-            if result.is_err() || !result.unwrap().success() {
-                panic!("Failed to create object file for {:?}", self.benchmark_name)
-            }
+            &self.manifest.build_target
+        };
+        let status = Command::new("make")
+            .arg(target)
+            .current_dir(&self.src_dir)
+            .status()?;
+        if !status.success() {
+            return Err(BenchmarkError::ExitStatus(status));
         }
 
-        // Create the object file
-        let mut origin = self.src_dir.clone();
-        origin.push(Path::new(&self.get_object_file()));
+        let object_dir = self.get_object_dir();
+        std::fs::create_dir_all(&object_dir)?;
+        for object in &self.manifest.objects {
+            let mut origin = self.src_dir.clone();
+            origin.push(object);
 
-        let mut dest_path = PathBuf::from(OBJECT_DIR);
-        dest_path.push(&self.get_object_file());
+            let mut dest_path = object_dir.clone();
+            dest_path.push(object);
 
-        // Move the object file to the objects folder
-        if !Command::new("mv")
-            .arg(origin)
-            .arg(dest_path)
-            .status()?
-            .success() {
-            panic!("Failed to move file");
+            // Move the object file to the objects folder
+            if std::fs::rename(&origin, &dest_path).is_err() {
+                std::fs::copy(&origin, &dest_path)?;
+                std::fs::remove_file(&origin)?;
+            }
         }
 
         Ok(())
@@ -141,27 +352,26 @@ impl Benchmark {
     /// Creates all of the benchmark binaries for each allocator
     ///
     /// Returns an error if it could not successfully create the binary files
-    pub fn create_binaries_for(self, allocators: &Vec<Option<String>>) -> Result<(), BenchmarkError> {
+    pub fn create_binaries_for(&self, allocators: &Vec<Option<String>>) -> Result<(), BenchmarkError> {
         Self::create_bin_dir();
 
-        let object_file = {
-            let mut path = PathBuf::from(OBJECT_DIR);
-            path.push(self.get_object_file());
-            path
-        };
+        let object_dir = self.get_object_dir();
+        let object_files: Vec<PathBuf> = self.manifest.objects.iter().map(|o| object_dir.join(o)).collect();
 
-        if !object_file.exists() {
-            return Err(BenchmarkError::IO(std::io::Error::last_os_error()));
+        for object_file in &object_files {
+            if !object_file.exists() {
+                return Err(BenchmarkError::IO(std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    format!("{:?} was not created", object_file)
+                )));
+            }
         }
 
         for allocator in allocators {
 
+            let output_path = binary_path(&self.get_name(), allocator.as_deref());
             let allocator = allocator.as_ref().map_or(String::from("libc"), |a| a.clone());
 
-            let output = format!("{}-{}", self.benchmark_name.to_str().unwrap(), allocator);
-            let mut output_path = PathBuf::from(BINARY_DIR);
-            output_path.push(output);
-
             let lib_args: Option<PathBuf> = if allocator != "libc" {
                 Some(PathBuf::from_iter(&[LIBRARY_DIR, & *format!("lib{}.a", allocator)]))
             } else {
@@ -175,8 +385,8 @@ impl Benchmark {
                 }
             }
             command.arg("-ldl");
-            command.args(&["-o", output_path.to_str().unwrap()])
-                .arg(object_file.to_str().unwrap());
+            command.args(["-o", output_path.to_str().unwrap()])
+                .args(&object_files);
 
 
             command
@@ -202,19 +412,28 @@ impl Benchmark {
     pub fn get_name(&self) -> String {
         self.benchmark_name.to_str().unwrap().to_string()
     }
+
+    /// Whether the object files of the benchmark have been built
+    pub fn has_objects(&self) -> bool {
+        let object_dir = self.get_object_dir();
+        self.manifest.objects.iter().all(|o| object_dir.join(o).exists())
+    }
+}
+
+/// The path of the binary of a benchmark linked with the given library, or with libc for `None`
+pub fn binary_path(benchmark: &str, library: Option<&str>) -> PathBuf {
+    PathBuf::from_iter(&[BINARY_DIR, &*format!("{}-{}", benchmark, library.unwrap_or("libc"))])
 }
 
 pub fn get_available_benchmarks() -> Result<Vec<OsString>, std::io::Error> {
     let entries = std::fs::read_dir(BENCHMARK_DIR)?;
     let mut output = vec![];
-    for entry in entries {
-        if let Ok(entry) = entry {
-            let path = entry.path();
-            if path.is_dir() {
-                let name = path.file_name().unwrap();
-                if name != COMMON_DIR {
-                    output.push(path.into_os_string())
-                }
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            let name = path.file_name().unwrap();
+            if name != COMMON_DIR {
+                output.push(path.into_os_string())
             }
         }
     }
@@ -0,0 +1,205 @@
+use std::fs::File;
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::stats::{significantly_different, OutlierPolicy, Samples};
+
+/// The name of the bisection report inside of a results directory
+pub const BISECT_FILE: &str = "bisect.json";
+
+/// How a commit is judged
+pub struct BisectOptions {
+    /// The drop in throughput from the good commit, in percent, that counts as a regression
+    pub threshold: f64,
+    /// How many rounds of trials a commit can be given before it is judged without a significant difference
+    pub max_rounds: usize,
+    pub outliers: OutlierPolicy
+}
+
+/// What measuring one round of trials of a commit produced
+pub enum Measurement {
+    /// The throughput of every trial that isn't a warmup
+    Throughputs(Vec<f64>),
+    Crashed,
+    /// The commit could not be built, so it can't be judged
+    Unbuildable(String)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Verdict {
+    Good,
+    Bad,
+    Skipped
+}
+
+/// The measurements of one commit and how it was judged
+#[derive(Debug, Serialize)]
+pub struct Step {
+    pub commit: String,
+    pub throughput: Option<f64>,
+    /// The change in throughput from the good commit, in percent
+    pub change: Option<f64>,
+    pub significant: Option<bool>,
+    pub trials: usize,
+    pub verdict: Verdict,
+    /// Why the commit was skipped
+    pub reason: Option<String>
+}
+
+/// The outcome of a bisection
+#[derive(Debug, Serialize)]
+pub struct Bisection {
+    pub good: String,
+    pub bad: String,
+    pub threshold: f64,
+    /// The steps in the order they were made, starting with the bad commit
+    pub steps: Vec<Step>,
+    /// The first commit that regressed
+    pub first_bad: String,
+    /// Commits that could not be built between the last good commit and the first bad one,
+    /// any of which could be the actual first regression
+    pub skipped: Vec<String>
+}
+
+/// Judges commits against the good commit, measuring more rounds of trials while the difference isn't significant
+struct Judge<'a, M> {
+    measure: M,
+    good: &'a str,
+    options: &'a BisectOptions,
+    baseline: Vec<f64>
+}
+
+impl<M: FnMut(&str) -> Measurement> Judge<'_, M> {
+
+    fn step(commit: &str, verdict: Verdict, reason: Option<String>) -> Step {
+        Step {
+            commit: commit.to_string(),
+            throughput: None,
+            change: None,
+            significant: None,
+            trials: 0,
+            verdict,
+            reason
+        }
+    }
+
+    /// Judges the commit and prints the verdict
+    fn judge(&mut self, commit: &str) -> Step {
+        let step = self.measure_commit(commit);
+        match (step.change, &step.reason) {
+            (Some(change), _) => println!("{}: {:?} ({:+.2}% over {} trials)", commit, step.verdict, change, step.trials),
+            (None, Some(reason)) => println!("{}: {:?} ({})", commit, step.verdict, reason),
+            (None, None) => println!("{}: {:?}", commit, step.verdict)
+        }
+        step
+    }
+
+    fn measure_commit(&mut self, commit: &str) -> Step {
+        let mut throughputs = vec![];
+        for round in 0..self.options.max_rounds {
+            if round > 0 {
+                // The baseline gets more trials as well, so that the comparison stays balanced
+                if let Measurement::Throughputs(more) = (self.measure)(self.good) {
+                    self.baseline.extend(more);
+                }
+            }
+            match (self.measure)(commit) {
+                Measurement::Throughputs(more) => throughputs.extend(more),
+                Measurement::Crashed => return Self::step(commit, Verdict::Bad, Some(String::from("crashed"))),
+                Measurement::Unbuildable(e) => return Self::step(commit, Verdict::Skipped, Some(e))
+            }
+
+            let base = Samples::new(&self.baseline, self.options.outliers);
+            let new = Samples::new(&throughputs, self.options.outliers);
+            let change = (new.value() - base.value()) / base.value() * 100.0;
+            let significant = significantly_different(&base, &new);
+            let verdict = if change >= -self.options.threshold {
+                Some(Verdict::Good)
+            } else if significant == Some(true) {
+                Some(Verdict::Bad)
+            } else if round + 1 == self.options.max_rounds {
+                // Out of rounds, a drop is a regression unless it is known to be noise
+                Some(if significant == Some(false) { Verdict::Good } else { Verdict::Bad })
+            } else {
+                None
+            };
+            if let Some(verdict) = verdict {
+                return Step {
+                    commit: commit.to_string(),
+                    throughput: Some(new.value()),
+                    change: Some(change),
+                    significant,
+                    trials: throughputs.len(),
+                    verdict,
+                    reason: None
+                };
+            }
+        }
+        unreachable!("the last round always gives a verdict")
+    }
+}
+
+/// Finds the first of `commits` whose throughput is worse than that of `good` by more than the threshold
+///
+/// The commits are ordered from oldest to newest and end with the bad commit. `measure` runs one round of
+/// trials of a commit, including the good one. Commits that can't be built are skipped, like `git bisect skip`.
+pub fn bisect<M>(good: &str, commits: &[String], options: &BisectOptions, mut measure: M) -> Result<Bisection, String>
+    where M: FnMut(&str) -> Measurement {
+    let bad = commits.last().ok_or_else(|| String::from("There are no commits between the good and bad commits"))?;
+
+    let baseline = match measure(good) {
+        Measurement::Throughputs(throughputs) => throughputs,
+        Measurement::Crashed => return Err(format!("The good commit {} crashed", good)),
+        Measurement::Unbuildable(e) => return Err(format!("The good commit {} could not be built: {}", good, e))
+    };
+    let mut judge = Judge { measure, good, options, baseline };
+    let mut steps = vec![];
+
+    let step = judge.judge(bad);
+    if step.verdict != Verdict::Bad {
+        return Err(format!(
+            "The bad commit {} is not slower than the good commit {} by more than {}%{}",
+            bad,
+            good,
+            options.threshold,
+            step.change.map_or(String::new(), |change| format!(" (change: {:.2}%)", change))
+        ));
+    }
+    steps.push(step);
+
+    // Every commit before the first one that is judged good is good as well, like the good commit itself
+    let mut verdicts: Vec<Option<Verdict>> = vec![None; commits.len()];
+    *verdicts.last_mut().unwrap() = Some(Verdict::Bad);
+    loop {
+        let first_bad = verdicts.iter().position(|v| *v == Some(Verdict::Bad)).unwrap();
+        let last_good = verdicts[..first_bad].iter().rposition(|v| *v == Some(Verdict::Good));
+        let start = last_good.map_or(0, |i| i + 1);
+        let candidates: Vec<usize> = (start..first_bad).filter(|&i| verdicts[i].is_none()).collect();
+        if candidates.is_empty() {
+            return Ok(Bisection {
+                good: good.to_string(),
+                bad: bad.clone(),
+                threshold: options.threshold,
+                steps,
+                first_bad: commits[first_bad].clone(),
+                skipped: (start..first_bad).map(|i| commits[i].clone()).collect()
+            });
+        }
+
+        let next = candidates[candidates.len() / 2];
+        let step = judge.judge(&commits[next]);
+        verdicts[next] = Some(step.verdict);
+        steps.push(step);
+    }
+}
+
+impl Bisection {
+
+    /// Writes the report into the given results directory
+    pub fn save<P: AsRef<Path>>(&self, dir: P) -> Result<(), std::io::Error> {
+        let file = File::create(dir.as_ref().join(BISECT_FILE))?;
+        serde_json::to_writer_pretty(file, self).map_err(std::io::Error::other)
+    }
+}
@@ -0,0 +1,181 @@
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::allocators::AllocatorSpec;
+use crate::benchmark::{LIBRARY_DIR, OBJECT_DIR};
+use crate::grapher::GRAPHS_DIR;
+use crate::worktree::{prune_worktrees, worktrees, WORKTREE_DIR};
+use crate::{BENCHMARK_RESULTS, BINARY_DIR};
+
+/// Removes files and directories, and keeps track of what was removed
+#[derive(Default)]
+pub struct Cleaner {
+    removed: usize,
+    files: usize,
+    bytes: u64,
+    failures: usize
+}
+
+/// Formats a number of bytes with the largest unit that keeps it above 1
+fn format_size(bytes: u64) -> String {
+    let units = ["B", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < units.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, units[0])
+    } else {
+        format!("{:.1} {}", size, units[unit])
+    }
+}
+
+/// Counts the files below a path and their size, without following symbolic links
+fn measure(path: &Path) -> std::io::Result<(usize, u64)> {
+    let metadata = std::fs::symlink_metadata(path)?;
+    if !metadata.is_dir() {
+        return Ok((1, metadata.len()));
+    }
+    let mut total = (0, 0);
+    for entry in std::fs::read_dir(path)? {
+        let (files, bytes) = measure(&entry?.path())?;
+        total.0 += files;
+        total.1 += bytes;
+    }
+    Ok(total)
+}
+
+/// Whether the last modification of a path is older than the given age
+fn is_older(path: &Path, age: Duration) -> bool {
+    std::fs::symlink_metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+        .is_some_and(|elapsed| elapsed > age)
+}
+
+/// The entries of a directory, or nothing if it doesn't exist
+fn entries(dir: &Path) -> Vec<PathBuf> {
+    let mut entries: Vec<PathBuf> = std::fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .collect();
+    entries.sort();
+    entries
+}
+
+impl Cleaner {
+
+    /// Removes a file or a directory with everything in it, and prints what was removed. Paths that don't
+    /// exist are skipped.
+    pub fn remove<P: AsRef<Path>>(&mut self, path: P) {
+        let path = path.as_ref();
+        let (files, bytes) = match measure(path) {
+            Ok(measured) => measured,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return,
+            Err(e) => {
+                eprintln!("Could not remove {:?}: {}", path, e);
+                self.failures += 1;
+                return;
+            }
+        };
+        let result = if std::fs::symlink_metadata(path).is_ok_and(|m| m.is_dir()) {
+            std::fs::remove_dir_all(path)
+        } else {
+            std::fs::remove_file(path)
+        };
+        match result {
+            Ok(()) => {
+                println!("Removed {:?} ({} file{}, {})", path, files, if files == 1 { "" } else { "s" }, format_size(bytes));
+                self.removed += 1;
+                self.files += files;
+                self.bytes += bytes;
+            },
+            Err(e) => {
+                eprintln!("Could not remove {:?}: {}", path, e);
+                self.failures += 1;
+            }
+        }
+    }
+
+    /// Removes the libraries of every allocator and every worktree of their revisions
+    pub fn clean_all_allocators(&mut self, allocators: &[AllocatorSpec], run_commands: bool) {
+        for library in entries(Path::new(LIBRARY_DIR)) {
+            self.remove(library);
+        }
+        self.remove(WORKTREE_DIR);
+        for allocator in allocators {
+            prune_worktrees(allocator);
+            if run_commands {
+                self.clean_sources(allocator);
+            }
+        }
+    }
+
+    /// Removes the libraries of an allocator, including those of its feature sets and revisions, and the
+    /// worktrees of its revisions
+    pub fn clean_allocator(&mut self, allocator: &AllocatorSpec, run_commands: bool) {
+        if let Some(lib_name) = allocator.lib_name() {
+            let prefix = format!("lib{}", lib_name);
+            for library in entries(Path::new(LIBRARY_DIR)) {
+                let name = library.file_name().unwrap().to_string_lossy().to_string();
+                if name.strip_prefix(&prefix).is_some_and(|rest| rest.starts_with(['.', '-', '_'])) {
+                    self.remove(library);
+                }
+            }
+        }
+        for worktree in worktrees(allocator) {
+            self.remove(worktree);
+        }
+        prune_worktrees(allocator);
+        if run_commands {
+            self.clean_sources(allocator);
+        }
+    }
+
+    /// Removes the declared build outputs of an allocator and runs its clean commands
+    fn clean_sources(&mut self, allocator: &AllocatorSpec) {
+        for artifact in allocator.artifacts() {
+            self.remove(artifact);
+        }
+        if !allocator.has_clean_commands() {
+            return;
+        }
+        println!("Running the clean commands of {}", allocator.name());
+        if let Err(e) = allocator.clean() {
+            eprintln!("Failed to clean {}: {}", allocator.name(), e);
+            self.failures += 1;
+        }
+    }
+
+    /// Removes the object files and binaries of the benchmarks
+    pub fn clean_benchmarks(&mut self) {
+        self.remove(OBJECT_DIR);
+        self.remove(BINARY_DIR);
+    }
+
+    /// Removes the results of previous runs, or only those that are older than the given age
+    pub fn clean_results(&mut self, older_than: Option<Duration>) {
+        let results = entries(Path::new(GRAPHS_DIR)).into_iter()
+            .chain(entries(Path::new(BENCHMARK_RESULTS)));
+        for path in results {
+            if older_than.is_none_or(|age| is_older(&path, age)) {
+                self.remove(path);
+            }
+        }
+    }
+
+    /// Prints how much was removed in total. Returns whether everything could be removed.
+    pub fn report(&self) -> bool {
+        if self.removed == 0 {
+            println!("Nothing to remove");
+        } else {
+            println!("Removed {} file{} in total, {}", self.files, if self.files == 1 { "" } else { "s" }, format_size(self.bytes));
+        }
+        self.failures == 0
+    }
+}
@@ -0,0 +1,110 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::Path;
+
+use crate::results::{ResultSet, RunRecord};
+use crate::stats::{significantly_different, Samples};
+
+/// Identifies the same point in two result sets
+type PointKey = (String, String, BTreeMap<String, String>, usize);
+
+fn point_key(run: &RunRecord) -> PointKey {
+    (run.benchmark.clone(), run.allocator.clone(), run.params.clone(), run.threads)
+}
+
+fn samples_of(run: &RunRecord) -> Option<Samples> {
+    let throughputs = run.throughputs();
+    if throughputs.is_empty() {
+        None
+    } else {
+        Some(Samples::new(&throughputs, run.outliers))
+    }
+}
+
+/// Compares the throughput of every point that is in both the baseline and candidate results
+///
+/// Prints the relative change of each point and whether it is statistically significant. Returns
+/// whether any point regressed by more than `threshold` percent, unless the difference is known
+/// to be insignificant.
+pub fn compare(
+    baseline_dir: &Path,
+    candidate_dir: &Path,
+    threshold: f64,
+    benchmarks: Option<&[&str]>,
+    allocators: Option<&[&str]>
+) -> Result<bool, Box<dyn Error>> {
+    let load = |dir: &Path| ResultSet::load(dir)
+        .map_err(|e| format!("Could not load the results in {:?}: {}", dir, e));
+    let baseline = load(baseline_dir)?;
+    let candidate = load(candidate_dir)?;
+
+    let selected = |run: &&RunRecord| {
+        benchmarks.is_none_or(|b| b.contains(&run.benchmark.as_str()))
+            && allocators.is_none_or(|a| a.iter().any(|name| run.is_allocator(name)))
+    };
+    let baseline: BTreeMap<PointKey, &RunRecord> = baseline.runs.iter()
+        .filter(selected)
+        .map(|run| (point_key(run), run))
+        .collect();
+
+    println!(
+        "{:<12} {:<16} {:<28} {:>7} {:>12} {:>12} {:>9} {:>12}",
+        "benchmark", "allocator", "params", "threads", "baseline", "candidate", "change", "significant"
+    );
+
+    let mut compared = 0;
+    let mut regressed = false;
+    for run in candidate.runs.iter().filter(selected) {
+        let base_run = match baseline.get(&point_key(run)) {
+            Some(base_run) => base_run,
+            None => continue
+        };
+        compared += 1;
+
+        let (base, new) = match (samples_of(base_run), samples_of(run)) {
+            (Some(base), Some(new)) => (base, new),
+            (base, new) => {
+                let status = |samples: &Option<Samples>| if samples.is_some() { "ok" } else { "failed" };
+                println!(
+                    "{:<12} {:<16} {:<28} {:>7} {:>12} {:>12}",
+                    run.benchmark, run.allocator, run.swept_label(), run.threads, status(&base), status(&new)
+                );
+                // A point that used to work and now fails is a regression
+                if base.is_some() {
+                    regressed = true;
+                }
+                continue;
+            }
+        };
+
+        let change = (new.value() - base.value()) / base.value() * 100.0;
+        let significant = significantly_different(&base, &new);
+        let is_regression = change < -threshold && significant != Some(false);
+        if is_regression {
+            regressed = true;
+        }
+
+        println!(
+            "{:<12} {:<16} {:<28} {:>7} {:>12.3} {:>12.3} {:>8.2}% {:>12}{}",
+            run.benchmark,
+            run.allocator,
+            run.swept_label(),
+            run.threads,
+            base.value(),
+            new.value(),
+            change,
+            match significant {
+                Some(true) => "yes",
+                Some(false) => "no",
+                None => "unknown"
+            },
+            if is_regression { "  REGRESSION" } else { "" }
+        );
+    }
+
+    if compared == 0 {
+        return Err(format!("No points are in both {:?} and {:?}", baseline_dir, candidate_dir).into());
+    }
+
+    Ok(regressed)
+}
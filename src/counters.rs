@@ -0,0 +1,196 @@
+use std::collections::BTreeMap;
+use std::convert::TryInto;
+use std::fs::File;
+use std::io::Read;
+use std::sync::Once;
+
+/// An event counted by the kernel in every run of a benchmark
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Counter {
+    Cycles,
+    Instructions,
+    CacheMisses,
+    /// Misses of the data TLB on loads
+    DtlbMisses,
+    PageFaults,
+    /// The time the benchmark ran on a CPU, which is counted instead of cycles without hardware counters
+    TaskClock
+}
+
+/// The counters of the hardware, and the software counter that replaces each of them, if any
+const COUNTERS: [(Counter, Option<Counter>); 5] = [
+    (Counter::Cycles, Some(Counter::TaskClock)),
+    (Counter::Instructions, None),
+    (Counter::CacheMisses, None),
+    (Counter::DtlbMisses, None),
+    (Counter::PageFaults, None)
+];
+
+const PERF_TYPE_HARDWARE: u32 = 0;
+const PERF_TYPE_SOFTWARE: u32 = 1;
+const PERF_TYPE_HW_CACHE: u32 = 3;
+
+const PERF_FORMAT_TOTAL_TIME_ENABLED: u64 = 1 << 0;
+const PERF_FORMAT_TOTAL_TIME_RUNNING: u64 = 1 << 1;
+
+const DISABLED: u64 = 1 << 0;
+const INHERIT: u64 = 1 << 1;
+const EXCLUDE_KERNEL: u64 = 1 << 5;
+const EXCLUDE_HV: u64 = 1 << 6;
+const ENABLE_ON_EXEC: u64 = 1 << 12;
+
+const PERF_FLAG_FD_CLOEXEC: libc::c_ulong = 1 << 3;
+
+/// The first version of `struct perf_event_attr`, which every kernel with perf events accepts
+#[repr(C)]
+#[derive(Default)]
+struct PerfEventAttr {
+    kind: u32,
+    size: u32,
+    config: u64,
+    sample_period: u64,
+    sample_type: u64,
+    read_format: u64,
+    /// The bit fields of the struct, starting with `disabled`
+    flags: u64,
+    wakeup_events: u32,
+    bp_type: u32,
+    config1: u64
+}
+
+impl Counter {
+
+    /// The name of the counter in the results
+    pub fn name(&self) -> &'static str {
+        match self {
+            Counter::Cycles => "cycles",
+            Counter::Instructions => "instructions",
+            Counter::CacheMisses => "cache-misses",
+            Counter::DtlbMisses => "dtlb-misses",
+            Counter::PageFaults => "page-faults",
+            Counter::TaskClock => "task-clock"
+        }
+    }
+
+    /// The type and config of the event in `perf_event_attr`
+    fn event(&self) -> (u32, u64) {
+        match self {
+            Counter::Cycles => (PERF_TYPE_HARDWARE, 0),
+            Counter::Instructions => (PERF_TYPE_HARDWARE, 1),
+            Counter::CacheMisses => (PERF_TYPE_HARDWARE, 3),
+            // The data TLB (3), in the lowest byte, with reads (0) in the next and misses (1) in the third
+            Counter::DtlbMisses => (PERF_TYPE_HW_CACHE, 3 | (1 << 16)),
+            Counter::PageFaults => (PERF_TYPE_SOFTWARE, 2),
+            Counter::TaskClock => (PERF_TYPE_SOFTWARE, 1)
+        }
+    }
+
+    /// Opens the counter on the calling thread. It stays disabled in the thread itself, and is enabled in
+    /// the processes the thread starts when they exec, along with every thread and process they start.
+    fn open(&self, exclude_kernel: bool) -> std::io::Result<File> {
+        let (kind, config) = self.event();
+        let mut flags = DISABLED | INHERIT | ENABLE_ON_EXEC;
+        if exclude_kernel {
+            flags |= EXCLUDE_KERNEL | EXCLUDE_HV;
+        }
+        let attr = PerfEventAttr {
+            kind,
+            size: std::mem::size_of::<PerfEventAttr>() as u32,
+            config,
+            read_format: PERF_FORMAT_TOTAL_TIME_ENABLED | PERF_FORMAT_TOTAL_TIME_RUNNING,
+            flags,
+            ..PerfEventAttr::default()
+        };
+        perf_event_open(&attr)
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn perf_event_open(attr: &PerfEventAttr) -> std::io::Result<File> {
+    use std::os::unix::io::FromRawFd;
+
+    // SAFETY: the kernel only reads the attributes, up to their size
+    let fd = unsafe {
+        libc::syscall(libc::SYS_perf_event_open, attr as *const PerfEventAttr, 0, -1, -1, PERF_FLAG_FD_CLOEXEC)
+    };
+    if fd < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    // SAFETY: the file descriptor was just opened, and nothing else owns it
+    Ok(unsafe { File::from_raw_fd(fd as libc::c_int) })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn perf_event_open(_attr: &PerfEventAttr) -> std::io::Result<File> {
+    Err(std::io::Error::new(std::io::ErrorKind::Other, "perf events are only available on Linux"))
+}
+
+/// Counters for the next process started by the calling thread
+pub struct Counters {
+    open: Vec<(Counter, File)>
+}
+
+/// Makes sure that the counters that can't be opened are only reported once
+static REPORT_MISSING: Once = Once::new();
+
+impl Counters {
+
+    /// Opens every counter on the calling thread, which has to be the thread that starts the benchmark.
+    /// Hardware counters that aren't available are replaced by software ones, or left out.
+    pub fn open() -> Self {
+        let mut open = vec![];
+        let mut missing = vec![];
+        for (counter, fallback) in COUNTERS {
+            let error = match Self::open_counter(counter) {
+                Ok(opened) => {
+                    open.push(opened);
+                    continue;
+                },
+                Err(e) => e
+            };
+            match fallback.map(Self::open_counter) {
+                Some(Ok(opened)) => {
+                    missing.push(format!("{} can't be counted ({}), so {} is counted instead", counter.name(), error, opened.0.name()));
+                    open.push(opened);
+                },
+                _ => missing.push(format!("{} can't be counted ({})", counter.name(), error))
+            }
+        }
+        REPORT_MISSING.call_once(|| {
+            for message in &missing {
+                eprintln!("Warning: {}", message);
+            }
+        });
+        Self { open }
+    }
+
+    /// Opens a counter, only counting in user mode if the kernel doesn't allow more
+    fn open_counter(counter: Counter) -> std::io::Result<(Counter, File)> {
+        let file = counter.open(false).or_else(|e| match e.raw_os_error() {
+            Some(libc::EACCES) | Some(libc::EPERM) => counter.open(true),
+            _ => Err(e)
+        })?;
+        Ok((counter, file))
+    }
+
+    /// Reads the counts, once the process and everything it started has been reaped. Counts of counters
+    /// that had to share the hardware with others are scaled up to the whole run.
+    pub fn read(self) -> BTreeMap<String, u64> {
+        let mut counts = BTreeMap::new();
+        for (counter, mut file) in self.open {
+            let mut buffer = [0u8; 24];
+            if file.read_exact(&mut buffer).is_err() {
+                continue;
+            }
+            let field = |i: usize| u64::from_ne_bytes(buffer[i * 8..i * 8 + 8].try_into().unwrap());
+            let (value, enabled, running) = (field(0), field(1), field(2));
+            let value = if running > 0 && running < enabled {
+                (value as f64 * enabled as f64 / running as f64) as u64
+            } else {
+                value
+            };
+            counts.insert(counter.name().to_string(), value);
+        }
+        counts
+    }
+}
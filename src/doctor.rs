@@ -0,0 +1,136 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+use crate::allocators::Registry;
+use crate::benchmark::{get_available_benchmarks, Benchmark};
+use crate::submodules::{fix_command, source_state, SourceState};
+
+/// Programs that the platform runs itself, and what it needs them for
+const PLATFORM_TOOLS: [(&str, &str); 3] = [
+    ("cc", "linking the benchmarks"),
+    ("make", "compiling the benchmarks"),
+    ("git", "checking out the allocators")
+];
+
+/// How to get a missing program
+fn install_hint(program: &str) -> String {
+    match program {
+        "cc" => String::from("install a C compiler, such as gcc or clang (on Debian and Ubuntu: `apt install build-essential`)"),
+        "make" => String::from("install make (on Debian and Ubuntu: `apt install make`)"),
+        "cargo" | "rustc" => String::from("install Rust with rustup, see https://rustup.rs"),
+        "autoconf" => String::from("install autoconf (on Debian and Ubuntu: `apt install autoconf`), which autogen.sh uses to generate the configure script"),
+        program => format!("install {} or add it to the PATH", program)
+    }
+}
+
+/// Finds a program the way the shell would, in the directories of the PATH
+fn find_program(program: &str) -> Option<PathBuf> {
+    if program.contains('/') {
+        return Some(PathBuf::from(program)).filter(|path| path.is_file());
+    }
+    let path = std::env::var_os("PATH")?;
+    std::env::split_paths(&path)
+        .map(|dir| dir.join(program))
+        .find(|path| path.is_file())
+}
+
+/// Checks that everything needed to build the allocators and benchmarks is there, and prints what is missing
+/// and how to fix it. Returns whether no problems were found.
+pub fn doctor(registry: &Registry) -> bool {
+    let mut problems = 0;
+    let mut problem = |what: String, fix: String| {
+        problems += 1;
+        println!("  missing  {}\n           {}", what, fix);
+    };
+
+    // What each program is needed for
+    let mut tools: BTreeMap<&str, BTreeSet<String>> = BTreeMap::new();
+    for (program, reason) in PLATFORM_TOOLS {
+        tools.entry(program).or_default().insert(reason.to_string());
+    }
+    for allocator in registry.allocators() {
+        for command in allocator.commands() {
+            if let Some(program) = command.split_whitespace().next() {
+                tools.entry(program).or_default().insert(format!("building {}", allocator.name()));
+            }
+            if command.contains("autogen.sh") {
+                tools.entry("autoconf").or_default().insert(format!("building {}", allocator.name()));
+            }
+        }
+    }
+
+    println!("Programs:");
+    for (program, reasons) in &tools {
+        let reasons = reasons.iter().cloned().collect::<Vec<_>>().join(", ");
+        match find_program(program) {
+            Some(path) => println!("  ok       {} ({})", program, path.display()),
+            None => problem(format!("{}, needed for {}", program, reasons), install_hint(program))
+        }
+    }
+
+    println!("Allocator sources:");
+    for allocator in registry.allocators() {
+        let dir = match allocator.source_dir() {
+            Some(dir) => dir,
+            None => continue
+        };
+        let fix = format!("check them out with `{}`", fix_command(dir));
+        let pinned = match source_state(dir) {
+            SourceState::Missing => {
+                problem(format!("the sources of {} in {:?}", allocator.name(), dir), fix);
+                continue;
+            },
+            SourceState::Moved { pinned, current } => {
+                problem(
+                    format!(
+                        "the pinned commit {} of {}, which is checked out at {}",
+                        pinned,
+                        allocator.name(),
+                        current.as_deref().unwrap_or("an unknown commit")
+                    ),
+                    fix
+                );
+                continue;
+            },
+            SourceState::Pinned(commit) => format!("at its pinned commit {}", commit),
+            SourceState::Unpinned => String::from("not a submodule")
+        };
+        // Scripts run by a shell, such as autogen.sh, have to be part of the sources
+        let scripts: Vec<&Path> = allocator.commands().into_iter()
+            .filter_map(|command| {
+                let mut words = command.split_whitespace();
+                match words.next() {
+                    Some("sh") | Some("bash") => words.next().map(Path::new),
+                    _ => None
+                }
+            })
+            .collect();
+        match scripts.into_iter().find(|script| !dir.join(script).is_file()) {
+            Some(script) => problem(format!("{:?} in the sources of {}", script, allocator.name()), fix),
+            None => println!("  ok       {} ({}, {})", allocator.name(), dir.display(), pinned)
+        }
+    }
+
+    println!("Benchmarks:");
+    match get_available_benchmarks() {
+        Ok(mut benchmarks) => {
+            benchmarks.sort();
+            for path in benchmarks {
+                let path = PathBuf::from(path);
+                let name = path.file_name().unwrap().to_string_lossy().to_string();
+                match Benchmark::new(path) {
+                    Ok(_) => println!("  ok       {}", name),
+                    Err(e) => problem(format!("a valid manifest for {}", name), e.to_string())
+                }
+            }
+        },
+        Err(e) => problem(String::from("the benchmark sources"), format!("could not read them: {}", e))
+    }
+
+    if problems == 0 {
+        println!("No problems found");
+    } else {
+        println!("{} problem(s) found", problems);
+    }
+    problems == 0
+}
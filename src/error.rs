@@ -0,0 +1,176 @@
+use std::fmt::{Display, Formatter};
+use std::process::{exit, ExitStatus};
+
+use crate::allocators::AllocatorError;
+use crate::benchmark::BenchmarkError;
+use crate::grapher::output_dir;
+use crate::results::{FailureRecord, ResultFormat, ResultSet};
+
+/// Why a run of a benchmark failed
+#[derive(Debug)]
+pub enum RunFailure {
+    /// The binary could not be started
+    Spawn(std::io::Error),
+    /// The benchmark exited with an error, or was killed by a signal
+    Crashed(ExitStatus),
+    /// The output of the benchmark could not be saved
+    Output(std::io::Error)
+}
+
+/// A failure at one of the stages of testing the allocators. It stops the platform, with the exit code
+/// of the stage, unless `--keep-going` is given.
+#[derive(Debug)]
+pub enum PlatformError {
+    /// A benchmark could not be loaded, because its directory or its manifest is missing or invalid
+    Load {
+        benchmark: String,
+        error: BenchmarkError
+    },
+    Build {
+        allocator: String,
+        error: AllocatorError
+    },
+    /// A benchmark could not be compiled, or linked with an allocator
+    Link {
+        benchmark: String,
+        allocator: Option<String>,
+        error: BenchmarkError
+    },
+    Run {
+        benchmark: String,
+        series: String,
+        threads: usize,
+        failure: RunFailure
+    },
+    Graph {
+        graph: String,
+        error: String
+    },
+    /// The results of the run could not be saved
+    IO(std::io::Error)
+}
+
+impl From<std::io::Error> for RunFailure {
+    fn from(e: std::io::Error) -> Self {
+        RunFailure::Output(e)
+    }
+}
+
+impl From<std::io::Error> for PlatformError {
+    fn from(e: std::io::Error) -> Self {
+        PlatformError::IO(e)
+    }
+}
+
+impl Display for RunFailure {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RunFailure::Spawn(e) => write!(f, "could not be started: {}", e),
+            RunFailure::Crashed(status) => write!(f, "crashed with {}", status),
+            RunFailure::Output(e) => write!(f, "its output could not be saved: {}", e)
+        }
+    }
+}
+
+impl Display for PlatformError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PlatformError::Load { benchmark, error } => write!(f, "Failed to load the benchmark {}: {}", benchmark, error),
+            PlatformError::Build { allocator, error } => write!(f, "Failed to build {}: {}", allocator, error),
+            PlatformError::Link { benchmark, allocator: Some(allocator), error } =>
+                write!(f, "Failed to link {} with {}: {}", benchmark, allocator, error),
+            PlatformError::Link { benchmark, allocator: None, error } => write!(f, "Failed to compile {}: {}", benchmark, error),
+            PlatformError::Run { benchmark, series, threads, failure } =>
+                write!(f, "{} with {} and {} threads {}", benchmark, series, threads, failure),
+            PlatformError::Graph { graph, error } => write!(f, "Failed to draw the graph {}: {}", graph, error),
+            PlatformError::IO(e) => write!(f, "Could not save the results: {}", e)
+        }
+    }
+}
+
+impl PlatformError {
+
+    /// The exit code of the platform when it stops because of this failure
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            PlatformError::Link { .. } => 3,
+            PlatformError::Build { .. } => 4,
+            PlatformError::Run { .. } => 5,
+            PlatformError::Graph { .. } => 6,
+            PlatformError::IO(_) => 7,
+            PlatformError::Load { .. } => 8
+        }
+    }
+
+    /// The failure as it is stored in the results
+    pub fn record(&self) -> FailureRecord {
+        let (stage, benchmark, allocator, threads) = match self {
+            PlatformError::Load { benchmark, .. } => ("load", Some(benchmark.clone()), None, None),
+            PlatformError::Build { allocator, .. } => ("build", None, Some(allocator.clone()), None),
+            PlatformError::Link { benchmark, allocator, .. } => ("link", Some(benchmark.clone()), allocator.clone(), None),
+            PlatformError::Run { benchmark, series, threads, .. } => ("run", Some(benchmark.clone()), Some(series.clone()), Some(*threads)),
+            PlatformError::Graph { graph, .. } => ("graph", Some(graph.clone()), None, None),
+            PlatformError::IO(_) => ("results", None, None, None)
+        };
+        FailureRecord {
+            stage: stage.to_string(),
+            benchmark,
+            allocator,
+            threads,
+            message: self.to_string()
+        }
+    }
+}
+
+/// Decides what happens after a failure: either the platform stops, or, with `--keep-going`, the failure
+/// is recorded and the rest of the runs go on
+pub struct Failures {
+    keep_going: bool,
+    formats: Vec<ResultFormat>,
+    /// The exit code of the first failure
+    exit_code: Option<i32>
+}
+
+impl Failures {
+
+    pub fn new(keep_going: bool, formats: &[ResultFormat]) -> Self {
+        Self {
+            keep_going,
+            formats: formats.to_vec(),
+            exit_code: None
+        }
+    }
+
+    /// Reports the failure and records it in the results. Returns whether the platform should go on.
+    pub fn record(&mut self, error: PlatformError, results: &mut ResultSet) -> bool {
+        eprintln!("{}", error);
+        results.failures.push(error.record());
+        self.exit_code.get_or_insert(error.exit_code());
+        self.keep_going
+    }
+
+    /// Saves the results, if anything was run or failed
+    fn save(&self, results: &ResultSet) {
+        if results.runs.is_empty() && results.failures.is_empty() {
+            return;
+        }
+        if let Err(e) = results.save(output_dir(), &self.formats) {
+            eprintln!("{}", PlatformError::IO(e));
+        }
+    }
+
+    /// Saves the results and exits with the code of the first failure
+    pub fn stop(&self, results: &ResultSet) -> ! {
+        self.save(results);
+        exit(self.exit_code.unwrap_or(1));
+    }
+
+    /// Saves the results, and if anything failed, exits with the code of the first failure
+    pub fn finish(&self, results: &ResultSet) {
+        self.save(results);
+        if self.exit_code.is_some() {
+            eprintln!("{} failure(s) were recorded in the results", results.failures.len());
+            self.stop(results);
+        }
+    }
+}
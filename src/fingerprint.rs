@@ -0,0 +1,204 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use serde::{Deserialize, Serialize};
+
+use crate::metadata::Toolchain;
+
+/// The extension of the fingerprint stored next to a built library
+pub const FINGERPRINT_EXTENSION: &str = "fingerprint";
+
+/// Directories that are never part of the sources of an allocator that isn't a git repository
+const IGNORED_DIRS: [&str; 2] = [".git", "target"];
+
+/// Everything that a built library depends on. A library only has to be rebuilt when this changes.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Fingerprint {
+    /// A hash of the contents of the tracked source files
+    pub sources: String,
+    pub dynamic: bool,
+    pub debug: bool,
+    pub features: Vec<String>,
+    /// The prepare and build commands, and the artifact they produce, with the placeholders filled in
+    pub commands: Vec<String>,
+    pub toolchain: Toolchain
+}
+
+/// Incrementally computes a 64 bit FNV-1a hash, which stays the same across runs and platforms
+struct Hasher(u64);
+
+impl Hasher {
+    fn new() -> Self {
+        Hasher(0xcbf29ce484222325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0 ^ *byte as u64).wrapping_mul(0x100000001b3);
+        }
+    }
+
+    /// Hashes the length before the bytes, so that the boundaries between fields matter
+    fn write_field(&mut self, bytes: &[u8]) {
+        self.write(&(bytes.len() as u64).to_le_bytes());
+        self.write(bytes);
+    }
+}
+
+/// The files of the sources, relative to the source directory. For git repositories these are the
+/// tracked files, and otherwise every file outside of `.git` and `target`.
+fn source_files(dir: &Path) -> Result<Vec<PathBuf>, std::io::Error> {
+    let output = Command::new("git").arg("-C").arg(dir).args(["ls-files", "-z"]).output();
+    if let Ok(output) = output {
+        if output.status.success() {
+            let mut files: Vec<PathBuf> = output.stdout
+                .split(|byte| *byte == 0)
+                .filter(|name| !name.is_empty())
+                .map(|name| PathBuf::from(String::from_utf8_lossy(name).to_string()))
+                .collect();
+            files.sort();
+            return Ok(files);
+        }
+    }
+
+    let mut files = vec![];
+    let mut dirs = vec![PathBuf::new()];
+    while let Some(relative) = dirs.pop() {
+        for entry in std::fs::read_dir(dir.join(&relative))? {
+            let entry = entry?;
+            let path = relative.join(entry.file_name());
+            if entry.file_type()?.is_dir() {
+                if !IGNORED_DIRS.iter().any(|ignored| entry.file_name() == *ignored) {
+                    dirs.push(path);
+                }
+            } else {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Hashes the names and contents of the source files in a directory
+pub fn hash_sources(dir: &Path) -> Result<String, std::io::Error> {
+    let mut hasher = Hasher::new();
+    let mut buffer = vec![];
+    for file in source_files(dir)? {
+        let path = dir.join(&file);
+        // Tracked files that were deleted, and submodules, are left out
+        if !path.is_file() {
+            continue;
+        }
+        buffer.clear();
+        File::open(&path)?.read_to_end(&mut buffer)?;
+        hasher.write_field(file.to_string_lossy().as_bytes());
+        hasher.write_field(&buffer);
+    }
+    Ok(format!("{:016x}", hasher.0))
+}
+
+impl Fingerprint {
+
+    /// Where the fingerprint of a library is stored
+    fn path(library: &Path) -> PathBuf {
+        let mut name = library.as_os_str().to_os_string();
+        name.push(".");
+        name.push(FINGERPRINT_EXTENSION);
+        PathBuf::from(name)
+    }
+
+    /// Whether the library exists and was built with this fingerprint
+    pub fn matches(&self, library: &Path) -> bool {
+        if !library.exists() {
+            return false;
+        }
+        std::fs::read_to_string(Self::path(library))
+            .ok()
+            .and_then(|text| serde_json::from_str::<Fingerprint>(&text).ok())
+            .is_some_and(|stored| stored == *self)
+    }
+
+    /// Stores the fingerprint next to the library that was built with it
+    pub fn save(&self, library: &Path) -> Result<(), std::io::Error> {
+        let file = File::create(Self::path(library))?;
+        serde_json::to_writer_pretty(file, self).map_err(std::io::Error::other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A directory of its own under the system temporary directory, which starts out empty
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("lrmalloc-rs-testing-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn fingerprint(sources: &str) -> Fingerprint {
+        Fingerprint {
+            sources: sources.to_string(),
+            dynamic: true,
+            debug: false,
+            features: vec![String::from("track_allocation")],
+            commands: vec![String::from("cargo build --release")],
+            toolchain: Toolchain {
+                cc: Some(String::from("cc 12")),
+                rustc: None
+            }
+        }
+    }
+
+    #[test]
+    fn hasher_is_fnv1a() {
+        let mut hasher = Hasher::new();
+        hasher.write(b"a");
+        assert_eq!(hasher.0, 0xaf63dc4c8601ec8c);
+    }
+
+    #[test]
+    fn saved_fingerprints_match() {
+        let dir = temp_dir("fingerprint");
+        let library = dir.join("liballoc.so");
+        assert!(!fingerprint("1").matches(&library));
+
+        std::fs::write(&library, b"").unwrap();
+        assert!(!fingerprint("1").matches(&library));
+        fingerprint("1").save(&library).unwrap();
+        assert!(dir.join("liballoc.so.fingerprint").is_file());
+        assert!(fingerprint("1").matches(&library));
+        assert!(!fingerprint("2").matches(&library));
+        assert!(!Fingerprint { debug: true, ..fingerprint("1") }.matches(&library));
+
+        std::fs::write(dir.join("liballoc.so.fingerprint"), b"not json").unwrap();
+        assert!(!fingerprint("1").matches(&library));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn source_hashes_follow_names_and_contents() {
+        let dir = temp_dir("sources");
+        std::fs::create_dir_all(dir.join("src")).unwrap();
+        std::fs::create_dir_all(dir.join("target")).unwrap();
+        std::fs::write(dir.join("src/lib.rs"), b"fn a() {}").unwrap();
+        let hash = hash_sources(&dir).unwrap();
+        assert_eq!(hash.len(), 16);
+
+        // Build outputs aren't sources
+        std::fs::write(dir.join("target/out"), b"built").unwrap();
+        assert_eq!(hash_sources(&dir).unwrap(), hash);
+
+        std::fs::write(dir.join("src/lib.rs"), b"fn b() {}").unwrap();
+        let changed = hash_sources(&dir).unwrap();
+        assert_ne!(changed, hash);
+
+        std::fs::rename(dir.join("src/lib.rs"), dir.join("src/main.rs")).unwrap();
+        assert_ne!(hash_sources(&dir).unwrap(), changed);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
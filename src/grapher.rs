@@ -1,19 +1,359 @@
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::error::Error;
 use std::iter::FromIterator;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use chrono::Local;
 use lazy_static::lazy_static;
-use random_color::RandomColor;
+use plotters::style::RGBColor;
+use serde::Deserialize;
+
+use crate::metadata::HostInfo;
+use crate::results::{Metric, RunRecord};
+use crate::sampler::{load_samples, MemorySample, MEMORY_DIR};
+use crate::stats::Samples;
+
+/// The points of a line in a graph, as the number of threads and the samples measured with that many threads
+pub type Series = Vec<(usize, Samples)>;
 
 pub struct Graph<'a> {
     benchmark: String,
-    results: HashMap<&'a str, Vec<f64>>,
-    num_threads: usize
+    results: BTreeMap<String, Series>,
+    /// The thread counts at which each series failed
+    failures: BTreeMap<String, Vec<usize>>,
+    options: &'a GraphOptions
+}
+
+/// Options that change how graphs look
+#[derive(Clone)]
+pub struct GraphOptions {
+    /// The title of the graph, where `{benchmark}` is replaced by the name of the benchmark
+    pub title: String,
+    pub x_desc: String,
+    pub y_desc: String,
+    pub width: u32,
+    pub height: u32,
+    /// Whether to draw the min/max error bars and the confidence band around each line
+    pub error_bars: bool,
+    /// Styles that replace the default style of a series, by series or allocator name
+    pub styles: BTreeMap<String, SeriesStyle>,
+    /// Thread counts that are marked with a labelled vertical line, such as the number of cores
+    pub thread_markers: Vec<(usize, String)>
+}
+
+/// The shape drawn at every point of a series
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Marker {
+    Circle,
+    Square,
+    Triangle,
+    Diamond,
+    Cross,
+    Star
+}
+
+const MARKERS: [Marker; 6] = [Marker::Circle, Marker::Square, Marker::Triangle, Marker::Diamond, Marker::Cross, Marker::Star];
+
+/// How the line of a series is drawn
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LineStyle {
+    Solid,
+    Dashed,
+    Dotted,
+    DashDot
+}
+
+const LINE_STYLES: [LineStyle; 4] = [LineStyle::Solid, LineStyle::Dashed, LineStyle::Dotted, LineStyle::DashDot];
+
+/// How a series looks. Missing fields use the default style of the series
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SeriesStyle {
+    /// The color, as #rrggbb
+    pub color: Option<String>,
+    pub marker: Option<Marker>,
+    pub line: Option<LineStyle>
+}
+
+/// The Okabe-Ito palette, which stays distinguishable with color blindness. Its yellow is left out,
+/// as it is hard to see on white
+const PALETTE: [(u8, u8, u8); 7] = [
+    (0, 0, 0),
+    (213, 94, 0),
+    (0, 114, 178),
+    (0, 158, 115),
+    (204, 121, 167),
+    (230, 159, 0),
+    (86, 180, 233)
+];
+
+/// The fixed styles of the allocators that come with the platform, as (palette index, marker, line)
+const DEFAULT_STYLES: [(&str, usize, Marker, LineStyle); 4] = [
+    ("libc", 0, Marker::Circle, LineStyle::Solid),
+    ("apfmalloc", 1, Marker::Square, LineStyle::Solid),
+    ("jemalloc", 2, Marker::Triangle, LineStyle::Dashed),
+    ("lrmalloc", 3, Marker::Diamond, LineStyle::Dotted)
+];
+
+/// A hash of the name that is the same in every run, unlike the hashers of the standard library
+fn stable_hash(name: &str) -> u64 {
+    // FNV-1a
+    name.bytes().fold(0xcbf29ce484222325, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3))
+}
+
+/// The allocator that a series is a variant of, which is the series itself if it isn't a variant
+fn base_allocator(series: &str) -> &str {
+    series.split(['[', '@']).next().unwrap()
+}
+
+/// The palette index of an allocator that doesn't come with the platform, which no other allocator of the graph
+/// has unless there are more allocators than colors. The allocators of the graph pick in the order of their names.
+fn free_color(allocator: &str, graph: &[&str]) -> usize {
+    let preferred = |name: &str| (stable_hash(name) % PALETTE.len() as u64) as usize;
+    let mut others: Vec<&str> = graph.iter()
+        .map(|series| base_allocator(series))
+        .filter(|name| !DEFAULT_STYLES.iter().any(|(default, ..)| default == name))
+        .collect();
+    others.sort_unstable();
+    others.dedup();
+
+    // The colors of the allocators that come with the platform are never taken, so that they are recognizable
+    let mut taken: Vec<usize> = DEFAULT_STYLES.iter().map(|(_, color, ..)| *color).collect();
+    for other in others {
+        let start = preferred(other);
+        let index = (0..PALETTE.len())
+            .map(|offset| (start + offset) % PALETTE.len())
+            .find(|index| !taken.contains(index))
+            .unwrap_or(start);
+        if other == allocator {
+            return index;
+        }
+        taken.push(index);
+    }
+    preferred(allocator)
+}
+
+/// One of the combinations of a marker and a line. The first half pairs markers and lines at indices of the
+/// same parity and the second half those of different parities, so that every combination comes up once
+fn marker_and_line(index: usize) -> (Marker, LineStyle) {
+    let combinations = MARKERS.len() * LINE_STYLES.len();
+    let index = index % combinations;
+    let shift = index / (combinations / 2);
+    (MARKERS[index % MARKERS.len()], LINE_STYLES[(index + shift) % LINE_STYLES.len()])
+}
+
+/// Parses a color written as #rrggbb
+pub fn parse_color(color: &str) -> Result<RGBColor, String> {
+    let hex = color.strip_prefix('#').unwrap_or(color);
+    if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("{} is not a color in the form #rrggbb", color));
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).unwrap();
+    Ok(RGBColor(channel(0), channel(2), channel(4)))
+}
+
+impl Marker {
+
+    /// The outline of the marker around (0, 0), in pixels
+    fn outline(&self, size: i32) -> Vec<(i32, i32)> {
+        let polygon = |corners: usize, radius: f64, rotation: f64| -> Vec<(i32, i32)> {
+            (0..corners)
+                .map(|i| {
+                    let angle = rotation + i as f64 * 2.0 * std::f64::consts::PI / corners as f64;
+                    ((radius * angle.cos()).round() as i32, (radius * angle.sin()).round() as i32)
+                })
+                .collect()
+        };
+        let size_f = size as f64;
+        match self {
+            Marker::Circle => polygon(16, size_f, 0.0),
+            Marker::Square => vec![(-size, -size), (size, -size), (size, size), (-size, size)],
+            Marker::Triangle => polygon(3, size_f * 1.3, -std::f64::consts::FRAC_PI_2),
+            Marker::Diamond => polygon(4, size_f * 1.3, 0.0),
+            Marker::Cross => {
+                let w = (size / 3).max(1);
+                vec![
+                    (-w, -size), (w, -size), (w, -w), (size, -w), (size, w), (w, w),
+                    (w, size), (-w, size), (-w, w), (-size, w), (-size, -w), (-w, -w)
+                ]
+            },
+            Marker::Star => {
+                polygon(10, size_f * 1.3, -std::f64::consts::FRAC_PI_2)
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, (x, y))| if i % 2 == 1 { (x / 2, y / 2) } else { (x, y) })
+                    .collect()
+            }
+        }
+    }
+}
+
+impl LineStyle {
+
+    /// The lengths, in pixels, of the alternating drawn and skipped parts of the line
+    fn pattern(&self) -> &'static [f64] {
+        match self {
+            LineStyle::Solid => &[],
+            LineStyle::Dashed => &[12.0, 6.0],
+            LineStyle::Dotted => &[3.0, 5.0],
+            LineStyle::DashDot => &[12.0, 5.0, 3.0, 5.0]
+        }
+    }
+
+    /// Splits a line through the points into the parts that are drawn
+    fn segments(&self, points: &[(i32, i32)]) -> Vec<Vec<(i32, i32)>> {
+        let pattern = self.pattern();
+        if pattern.is_empty() {
+            return vec![points.to_vec()];
+        }
+
+        let mut segments = vec![];
+        let mut current = vec![];
+        let mut index = 0;
+        let mut remaining = pattern[0];
+        for pair in points.windows(2) {
+            let (start, end) = ((pair[0].0 as f64, pair[0].1 as f64), (pair[1].0 as f64, pair[1].1 as f64));
+            let length = ((end.0 - start.0).powi(2) + (end.1 - start.1).powi(2)).sqrt();
+            let at = |distance: f64| {
+                let fraction = if length == 0.0 { 0.0 } else { distance / length };
+                ((start.0 + (end.0 - start.0) * fraction).round() as i32, (start.1 + (end.1 - start.1) * fraction).round() as i32)
+            };
+
+            let mut travelled = 0.0;
+            while travelled < length {
+                let step = remaining.min(length - travelled);
+                let drawn = index % 2 == 0;
+                if drawn {
+                    if current.is_empty() {
+                        current.push(at(travelled));
+                    }
+                    current.push(at(travelled + step));
+                }
+                travelled += step;
+                remaining -= step;
+                if remaining <= 0.0 {
+                    if drawn {
+                        segments.push(std::mem::take(&mut current));
+                    }
+                    index = (index + 1) % pattern.len();
+                    remaining = pattern[index];
+                }
+            }
+        }
+        if current.len() > 1 {
+            segments.push(current);
+        }
+        segments
+    }
+}
+
+/// The final style of a series
+struct ResolvedStyle {
+    color: RGBColor,
+    marker: Marker,
+    line: LineStyle
+}
+
+impl GraphOptions {
+
+    /// The style of a series among the series of one graph, which are all drawn differently
+    ///
+    /// The allocators that come with the platform have fixed styles. Other allocators take a color that no other
+    /// allocator of the graph has, preferring one chosen from their name. Variants of an allocator, named like
+    /// `apfmalloc[no_met_stack]` or `apfmalloc@master`, get the color of the allocator, and a marker and line that
+    /// neither the allocator nor its other variants have, in the order of their names.
+    fn resolve_style(&self, series: &str, graph: &[&str]) -> ResolvedStyle {
+        let allocator = base_allocator(series);
+        let (mut color, mut marker, mut line) = match DEFAULT_STYLES.iter().find(|(name, ..)| *name == allocator) {
+            Some((_, color, marker, line)) => (PALETTE[*color], *marker, *line),
+            None => {
+                let hash = stable_hash(allocator);
+                (
+                    PALETTE[free_color(allocator, graph)],
+                    MARKERS[(hash / 7 % MARKERS.len() as u64) as usize],
+                    LINE_STYLES[(hash / 11 % LINE_STYLES.len() as u64) as usize]
+                )
+            }
+        };
+        if series != allocator {
+            let mut variants: Vec<&str> = graph.iter()
+                .copied()
+                .filter(|other| *other != allocator && base_allocator(other) == allocator)
+                .collect();
+            variants.sort_unstable();
+            variants.dedup();
+            let index = variants.iter().position(|variant| *variant == series).unwrap_or(variants.len());
+            let taken = (marker, line);
+            (marker, line) = (0..).map(marker_and_line).filter(|pair| *pair != taken).nth(index).unwrap();
+        }
+
+        for name in [allocator, series] {
+            if let Some(style) = self.styles.get(name) {
+                if let Some(c) = style.color.as_ref().and_then(|c| parse_color(c).ok()) {
+                    color = (c.0, c.1, c.2);
+                }
+                marker = style.marker.unwrap_or(marker);
+                line = style.line.unwrap_or(line);
+            }
+        }
+
+        ResolvedStyle {
+            color: RGBColor(color.0, color.1, color.2),
+            marker,
+            line
+        }
+    }
 }
 
-const GRAPHS_DIR: &str = "./graphs";
+pub const DEFAULT_TITLE: &str = "{benchmark} Throughput vs Number of Threads";
+
+impl Default for GraphOptions {
+    fn default() -> Self {
+        Self {
+            title: DEFAULT_TITLE.to_string(),
+            x_desc: String::from("Number of Threads"),
+            y_desc: String::from("Throughput"),
+            width: 900,
+            height: 600,
+            error_bars: true,
+            styles: BTreeMap::new(),
+            thread_markers: vec![]
+        }
+    }
+}
+
+impl GraphOptions {
+
+    /// The options for the graphs of a metric other than throughput, which are titled and labelled after it
+    pub fn for_metric(&self, graph: &str, metric: Metric) -> GraphOptions {
+        if metric == Metric::Throughput {
+            return self.clone();
+        }
+        GraphOptions {
+            title: format!("{} {} vs Number of Threads", graph, metric.label()),
+            y_desc: metric.label().to_string(),
+            ..self.clone()
+        }
+    }
+}
+
+/// Markers at the number of physical cores and logical CPUs of a machine, past which threads share a core,
+/// and then a CPU
+pub fn topology_markers(host: &HostInfo) -> Vec<(usize, String)> {
+    let plural = |count: usize, unit: &str| format!("{} {}{}", count, unit, if count == 1 { "" } else { "s" });
+    if host.physical_cores == host.logical_cpus {
+        vec![(host.logical_cpus, plural(host.logical_cpus, "core"))]
+    } else {
+        vec![
+            (host.physical_cores, plural(host.physical_cores, "core")),
+            (host.logical_cpus, plural(host.logical_cpus, "CPU"))
+        ]
+    }
+}
+
+pub const GRAPHS_DIR: &str = "./graphs";
 
 lazy_static! {
     pub static ref NEW_DIR: String = format!(
@@ -23,118 +363,233 @@ lazy_static! {
 }
 
 
-fn generate_graph_path(benchmark_name: &str) -> PathBuf {
-    let graph_name = format!("{}.png", benchmark_name);
+/// The directory where the graphs and results of this run are stored
+pub fn output_dir() -> PathBuf {
     let path =  PathBuf::from_iter(&[GRAPHS_DIR, &*NEW_DIR]);
-    std::fs::create_dir_all(path).unwrap();
-    PathBuf::from_iter(&[GRAPHS_DIR, &*NEW_DIR, & *graph_name])
+    std::fs::create_dir_all(&path).unwrap();
+    path
+}
+
+/// Replaces the characters of a graph or series name that can't be part of a file name, such as the `/` of
+/// a git branch
+pub fn file_name(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_control() || matches!(c, '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|') { '_' } else { c })
+        .collect()
+}
+
+/// The name of the graph of a benchmark, including the swept parameters (`name=value,...`) if there are any
+pub fn graph_name(benchmark: &str, swept: &str) -> String {
+    if swept.is_empty() {
+        benchmark.to_string()
+    } else {
+        format!("{}[{}]", benchmark, swept)
+    }
+}
+
+/// The name of the graph of a metric, which is the graph name followed by the metric, except for throughput
+pub fn metric_graph_name(graph: &str, metric: Metric) -> String {
+    if metric == Metric::Throughput {
+        graph.to_string()
+    } else {
+        format!("{}-{}", graph, metric)
+    }
+}
+
+fn generate_graph_path(dir: &Path, benchmark_name: &str) -> PathBuf {
+    let graph_name = format!("{}.png", file_name(benchmark_name));
+    std::fs::create_dir_all(dir).unwrap();
+    dir.join(graph_name)
 }
 
 impl <'a> Graph<'a> {
 
-    pub fn new(benchmark: String, results: HashMap<&'a str, Vec<f64>>, num_threads: usize) -> Self {
+    pub fn new(benchmark: String, results: BTreeMap<String, Series>, options: &'a GraphOptions) -> Self {
         Self {
             benchmark,
             results,
-            num_threads
+            failures: BTreeMap::new(),
+            options
         }
     }
 
-    fn get_line_series(&self, allocator: &&'a str) -> impl Iterator<Item=(usize, f64)> {
+    /// Marks the points at which series failed, which are drawn as crosses on the x axis
+    pub fn with_failures(mut self, failures: BTreeMap<String, Vec<usize>>) -> Self {
+        self.failures = failures;
+        self
+    }
+
+    fn get_line_series(&self, allocator: &str) -> impl Iterator<Item=(usize, f64)> {
         let points =
             self.results[allocator]
                 .iter()
-                .enumerate()
-                .map(|(index, throughput)| (index + 1, *throughput))
+                .map(|(threads, samples)| (*threads, samples.value()))
                 .collect::<Vec<_>>()
                 .into_iter();
         points
     }
 
-    fn get_max_throughput(&self) -> f64 {
+    /// The outline of the 95% confidence interval band around the line of an allocator
+    fn get_confidence_band(&self, allocator: &str) -> Vec<(usize, f64)> {
+        let intervals: Vec<(usize, (f64, f64))> =
+            self.results[allocator]
+                .iter()
+                .map(|(threads, samples)| (*threads, samples.confidence_interval()))
+                .collect();
+        let upper = intervals.iter().map(|(x, (_, high))| (*x, *high));
+        let lower = intervals.iter().rev().map(|(x, (low, _))| (*x, *low));
+        upper.chain(lower).collect()
+    }
+
+    fn get_max_value(&self) -> f64 {
         let mut max = 0.0f64;
         for allocator in self.results.keys() {
             let results = &self.results[allocator];
-            for throughput in results {
-                max = max.max(*throughput);
+            for (_, samples) in results {
+                max = if self.options.error_bars {
+                    max.max(samples.max()).max(samples.confidence_interval().1)
+                } else {
+                    max.max(samples.value())
+                };
             }
         }
         max
     }
 
-    pub fn make_graph(self) -> Result<(), Box<dyn Error>> {
+    /// The smallest and largest thread counts of any line or failure
+    fn get_thread_range(&self) -> (usize, usize) {
+        let threads = self.results.values()
+            .flat_map(|series| series.iter().map(|(threads, _)| *threads))
+            .chain(self.failures.values().flatten().copied());
+        let min = threads.clone().min().unwrap_or(1);
+        let max = threads.max().unwrap_or(1);
+        (min, max)
+    }
+
+    /// Draws the graph into the given directory
+    pub fn make_graph(self, dir: &Path) -> Result<(), Box<dyn Error>> {
         use plotters::prelude::*;
         println!("Generating graph");
-        let path = generate_graph_path(&*self.benchmark);
-        let root = BitMapBackend::new(
+        let path = generate_graph_path(dir, &self.benchmark);
+        let full = BitMapBackend::new(
             &path,
-            (900, 600)
+            (self.options.width, self.options.height)
         ).into_drawing_area();
-        let root = root.margin(10, 10, 10, 10);
+        let root = full.margin(10, 10, 10, 10);
         root.fill(&WHITE)?;
 
-        let max_y: f64 = self.get_max_throughput() + 10.0;
+        // Metrics such as CPU time are much smaller than throughputs, so the headroom is relative
+        let max = self.get_max_value();
+        let max_y: f64 = if max > 0.0 { max * 1.15 } else { 10.0 };
+
+        let (min_threads, max_threads) = self.get_thread_range();
+        // With a single thread count the axis would be empty, so it goes on to the next count
+        let max_threads = max_threads.max(min_threads + 1);
 
         let mut chart = ChartBuilder::on(&root)
-            .caption(format!("{} Throughput vs Number of Threads", self.benchmark), ("sans-serif", 30).into_font())
+            .caption(self.options.title.replace("{benchmark}", &self.benchmark), ("sans-serif", 30).into_font())
             .x_label_area_size(40)
             .margin_right(20)
             .y_label_area_size(60)
             .margin_bottom(10)
             .margin_left(10)
-            .build_ranged(1..self.num_threads, 0f64..max_y).unwrap();
+            .build_ranged(min_threads..max_threads, 0f64..max_y)?;
 
         chart
             .configure_mesh()
             .x_labels(16)
             .y_labels(10)
-            .x_desc("Number of Threads")
-            .y_desc("Throughput")
+            .x_desc(self.options.x_desc.as_str())
+            .y_desc(self.options.y_desc.as_str())
             .draw()?;
 
-        let mut created_colors: Vec<(u8, u8, u8)> = vec![];
+        let marker_style = ShapeStyle::from(&RGBColor(120, 120, 120));
+        for (threads, label) in &self.options.thread_markers {
+            if *threads < min_threads || *threads > max_threads {
+                continue;
+            }
+            let top = chart.backend_coord(&(*threads, max_y));
+            let bottom = chart.backend_coord(&(*threads, 0.0));
+            for segment in LineStyle::Dashed.segments(&[top, bottom]) {
+                full.draw(&PathElement::new(segment, marker_style.clone()))?;
+            }
+            full.draw(&Text::new(label.clone(), (top.0 + 4, top.1 + 4), ("sans-serif", 14).into_font().color(&RGBColor(120, 120, 120))))?;
+        }
 
+        let graph: Vec<&str> = self.results.keys().chain(self.failures.keys()).map(|series| series.as_str()).collect();
         for allocator in self.results.keys() {
-            let random_color = RandomColor::new()
-                .alpha(0.0)
-                .to_rgb_array();
-            let color = loop {
-                let out = RGBColor(random_color[0] as u8, random_color[1] as u8, random_color[2] as u8);
-                let RGBColor (r, g, b) = &out;
-                if !created_colors.contains(&(*r, *g, *b)) {
-                    break out;
-                }
-            };
-            {
-                let RGBColor (r, g, b) = &color;
-                created_colors.push((*r, *g, *b));
-            }
+            let style = self.options.resolve_style(allocator, &graph);
+            let color = style.color;
+            let marker = style.marker;
 
-            let series = self.get_line_series(allocator);
+            if self.options.error_bars {
+                chart.draw_series(
+                    std::iter::once(Polygon::new(self.get_confidence_band(allocator), &color.mix(0.2)))
+                )?;
 
+                chart.draw_series(
+                    self.results[allocator]
+                        .iter()
+                        .map(|(threads, samples)| {
+                            ErrorBar::new_vertical(*threads, samples.min(), samples.value(), samples.max(), color.filled(), 8)
+                        })
+                )?;
+            }
 
+            // Patterned lines are split in pixels, so they are drawn directly onto the backend
+            let line_style = {
+                let mut ret = ShapeStyle::from(&color);
+                ret.stroke_width = 3;
+                ret
+            };
+            let pixels: Vec<(i32, i32)> = self.get_line_series(allocator)
+                .map(|point| chart.backend_coord(&point))
+                .collect();
+            for segment in style.line.segments(&pixels) {
+                full.draw(&PathElement::new(segment, line_style.clone()))?;
+            }
+
+            // The legend always draws 4 parts of the line, repeating the first if there are fewer
+            let mut legend_line: Vec<Vec<(i32, i32)>> = style.line.segments(&[(0, 0), (25, 0)]);
+            legend_line.truncate(4);
+            while legend_line.len() < 4 {
+                legend_line.push(legend_line[0].clone());
+            }
             chart.draw_series(
-                LineSeries::new(series,
-                                {
-                                    let mut ret = ShapeStyle::from(&color);
-                                    ret.stroke_width = 3;
-                                    ret
-                                }
-                )
+                self.get_line_series(allocator)
+                    .map(|point| EmptyElement::at(point) + Polygon::new(marker.outline(5), color.filled()))
             )?
-                .label(format!("{}", allocator))
+                .label(allocator.to_string())
                 .legend(move |(base_x, base_y)| {
-                    let mut style = ShapeStyle::from(&color);
-                    style.filled = true;
-                    style.stroke_width = 10;
-                    Rectangle::new(
-                        [(base_x, base_y - 3), (base_x + 25, base_y + 3)],
-                        style
-                    )
+                    EmptyElement::at((base_x, base_y))
+                        + PathElement::new(legend_line[0].clone(), line_style.clone())
+                        + PathElement::new(legend_line[1].clone(), line_style.clone())
+                        + PathElement::new(legend_line[2].clone(), line_style.clone())
+                        + PathElement::new(legend_line[3].clone(), line_style.clone())
+                        + Polygon::new(marker.outline(4).into_iter().map(|(x, y)| (x + 12, y)).collect::<Vec<_>>(), color.filled())
                 }
                 );
         }
 
+        if self.failures.values().any(|failures| !failures.is_empty()) {
+            let cross = |color: RGBColor| {
+                let mut style = ShapeStyle::from(&color);
+                style.stroke_width = 3;
+                EmptyElement::at((0, 0))
+                    + PathElement::new(vec![(-6, -6), (6, 6)], style.clone())
+                    + PathElement::new(vec![(-6, 6), (6, -6)], style)
+            };
+            let crosses = self.failures.iter()
+                .flat_map(|(series, failures)| {
+                    let RGBColor(r, g, b) = self.options.resolve_style(series, &graph).color;
+                    failures.iter().map(move |threads| (*threads, RGBColor(r, g, b)))
+                })
+                .map(|(threads, color)| EmptyElement::at((threads, 0.0)) + cross(color));
+            chart.draw_series(crosses)?
+                .label("failed")
+                .legend(move |(base_x, base_y)| EmptyElement::at((base_x + 12, base_y)) + cross(BLACK));
+        }
+
         chart.configure_series_labels()
             .position(
                 SeriesLabelPosition::UpperRight
@@ -154,3 +609,160 @@ impl <'a> Graph<'a> {
     }
 }
 
+
+/// A graph of the RSS of one series over the time of a run, with a line for every thread count
+pub struct MemoryGraph<'a> {
+    name: String,
+    series: String,
+    /// The memory samples of one run at each thread count
+    lines: BTreeMap<usize, Vec<MemorySample>>,
+    options: &'a GraphOptions
+}
+
+/// The name of the memory graph of a series
+fn memory_graph_name(graph: &str, series: &str) -> String {
+    format!("{}-memory-{}", graph, series)
+}
+
+impl <'a> MemoryGraph<'a> {
+
+    /// Draws the graph into the given directory
+    pub fn make_graph(self, dir: &Path) -> Result<(), Box<dyn Error>> {
+        use plotters::prelude::*;
+        println!("Generating memory graph");
+        let path = generate_graph_path(dir, &memory_graph_name(&self.name, &self.series));
+        let root = BitMapBackend::new(
+            &path,
+            (self.options.width, self.options.height)
+        ).into_drawing_area();
+        let root = root.margin(10, 10, 10, 10);
+        root.fill(&WHITE)?;
+
+        let samples = self.lines.values().flatten();
+        let max_time = samples.clone().map(|s| s.time).fold(0.0, f64::max);
+        let max_rss = samples.map(|s| s.rss as f64 / 1024.0).fold(0.0, f64::max);
+
+        let mut chart = ChartBuilder::on(&root)
+            .caption(format!("{} Memory of {} over Time", self.name, self.series), ("sans-serif", 30).into_font())
+            .x_label_area_size(40)
+            .margin_right(20)
+            .y_label_area_size(60)
+            .margin_bottom(10)
+            .margin_left(10)
+            .build_ranged(0f64..max_time.max(0.001), 0f64..if max_rss > 0.0 { max_rss * 1.15 } else { 10.0 })?;
+
+        chart
+            .configure_mesh()
+            .x_labels(10)
+            .y_labels(10)
+            .x_desc("Time (s)")
+            .y_desc("RSS (MB)")
+            .draw()?;
+
+        for (i, (threads, samples)) in self.lines.iter().enumerate() {
+            let (r, g, b) = PALETTE[i % PALETTE.len()];
+            let mut style = ShapeStyle::from(&RGBColor(r, g, b));
+            style.stroke_width = 2;
+            chart.draw_series(LineSeries::new(samples.iter().map(|s| (s.time, s.rss as f64 / 1024.0)), style.clone()))?
+                .label(format!("{} thread{}", threads, if *threads == 1 { "" } else { "s" }))
+                .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], style.clone()));
+        }
+
+        chart.configure_series_labels()
+            .position(SeriesLabelPosition::UpperRight)
+            .border_style(ShapeStyle::from(&BLACK))
+            .background_style(ShapeStyle::from(&WHITE))
+            .draw()?;
+
+        Ok(())
+    }
+}
+
+/// Draws a memory graph for every series of a graph that had its memory sampled, from the first measured
+/// trial at each thread count. The samples are read from the folder of the run they were measured in.
+pub fn draw_memory_graphs<'r, I: Iterator<Item = &'r RunRecord>>(
+    graph: &str,
+    records: I,
+    run_dir: &Path,
+    options: &GraphOptions,
+    dir: &Path
+) -> Result<(), Box<dyn Error>> {
+    let mut series: BTreeMap<String, BTreeMap<usize, Vec<MemorySample>>> = BTreeMap::new();
+    for record in records {
+        let trial = record.trials.iter().find(|t| !t.warmup && t.success && t.memory.is_some());
+        if let Some(file) = trial.and_then(|t| t.memory.as_ref()).and_then(|path| path.file_name()) {
+            let samples = load_samples(&run_dir.join(MEMORY_DIR).join(file))?;
+            series.entry(record.allocator.clone()).or_default().insert(record.threads, samples);
+        }
+    }
+    for (series, lines) in series {
+        MemoryGraph {
+            name: graph.to_string(),
+            series,
+            lines,
+            options
+        }.make_graph(dir)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_names_replace_separators() {
+        assert_eq!(file_name("apfmalloc@feature/x"), "apfmalloc@feature_x");
+        assert_eq!(file_name("a\\b:c*d?e\"f<g>h|i"), "a_b_c_d_e_f_g_h_i");
+        assert_eq!(file_name("tab\there\nnewline"), "tab_here_newline");
+        assert_eq!(file_name("t-test1[size=64,bins=10]"), "t-test1[size=64,bins=10]");
+    }
+
+    #[test]
+    fn every_marker_and_line_comes_up_once() {
+        let combinations = MARKERS.len() * LINE_STYLES.len();
+        let pairs: Vec<(Marker, LineStyle)> = (0..combinations).map(marker_and_line).collect();
+        for (i, pair) in pairs.iter().enumerate() {
+            assert!(!pairs[i + 1..].contains(pair), "{:?} comes up twice", pair);
+        }
+        assert_eq!(marker_and_line(combinations), marker_and_line(0));
+    }
+
+    #[test]
+    fn unknown_allocators_get_free_colors() {
+        let graph = ["libc", "apfmalloc", "jemalloc", "lrmalloc", "a", "b", "c", "b[x]"];
+        let mut colors: Vec<usize> = ["a", "b", "c"].iter().map(|name| free_color(name, &graph)).collect();
+        assert!(colors.iter().all(|color| *color > 3), "{:?} takes a platform color", colors);
+        colors.sort_unstable();
+        colors.dedup();
+        assert_eq!(colors.len(), 3);
+    }
+
+    #[test]
+    fn variants_are_drawn_differently() {
+        let options = GraphOptions::default();
+        let graph = ["apfmalloc", "apfmalloc[a]", "apfmalloc[b]", "apfmalloc@master", "custom", "custom[a]"];
+        let styles: Vec<ResolvedStyle> = graph.iter().map(|series| options.resolve_style(series, &graph)).collect();
+        let color = |style: &ResolvedStyle| (style.color.0, style.color.1, style.color.2);
+        for (i, a) in styles.iter().enumerate() {
+            for (j, b) in styles.iter().enumerate().skip(i + 1) {
+                assert!(
+                    (color(a), a.marker, a.line) != (color(b), b.marker, b.line),
+                    "{} and {} have the same style", graph[i], graph[j]
+                );
+            }
+        }
+        // Variants keep the color of their allocator
+        assert_eq!(color(&styles[1]), PALETTE[1]);
+        assert_eq!(color(&styles[5]), color(&styles[4]));
+    }
+
+    #[test]
+    fn colors() {
+        let color = parse_color("#d55e00").unwrap();
+        assert_eq!((color.0, color.1, color.2), (213, 94, 0));
+        assert!(parse_color("0072B2").is_ok());
+        assert!(parse_color("#12345").is_err());
+        assert!(parse_color("#12345g").is_err());
+    }
+}
@@ -0,0 +1,85 @@
+use std::path::PathBuf;
+
+use crate::allocators::{AllocatorSpec, BuildOptions, Registry};
+use crate::benchmark::{binary_path, get_available_benchmarks, Benchmark};
+use crate::metadata::Toolchain;
+use crate::submodules::is_checked_out;
+
+/// Whether the library of an allocator has been built, and whether it was built with these options
+fn library_status(allocator: &AllocatorSpec, options: &BuildOptions, toolchain: &Toolchain) -> &'static str {
+    let library = match allocator.lib_file(options.dynamic) {
+        Some(library) if library.exists() => library,
+        _ => return "not built"
+    };
+    match allocator.fingerprint(options, toolchain) {
+        Ok(fingerprint) if fingerprint.matches(&library) => "up to date",
+        Ok(_) => "out of date",
+        Err(_) => "built, but its sources can't be checked"
+    }
+}
+
+/// Prints the allocators of the registry and the available benchmarks, and what of them has been built
+///
+/// Libraries are up to date when they were built from the current sources with the given options.
+pub fn list(registry: &Registry, options: &BuildOptions, toolchain: &Toolchain) {
+    println!("Allocators:");
+    let width = registry.names().iter().map(|name| name.len()).max().unwrap_or(0);
+    for allocator in registry.allocators() {
+        let mut status = vec![];
+        match allocator.source_dir() {
+            None => status.push(String::from("part of the system, nothing to build")),
+            Some(dir) => {
+                for dynamic in [false, true] {
+                    if allocator.can_build(dynamic) {
+                        let options = BuildOptions { dynamic, ..*options };
+                        status.push(format!(
+                            "{}: {}",
+                            if dynamic { "dynamic" } else { "static" },
+                            library_status(allocator, &options, toolchain)
+                        ));
+                    }
+                }
+                if !is_checked_out(dir) {
+                    status.push(format!("sources not checked out in {:?}", dir));
+                }
+            }
+        }
+        println!("  {:width$}  {}", allocator.name(), status.join(", "), width = width);
+    }
+
+    println!("Benchmarks:");
+    let mut benchmarks: Vec<PathBuf> = match get_available_benchmarks() {
+        Ok(benchmarks) => benchmarks.into_iter().map(PathBuf::from).collect(),
+        Err(e) => {
+            println!("  Could not read the benchmarks: {}", e);
+            return;
+        }
+    };
+    benchmarks.sort();
+    let names: Vec<String> = benchmarks.iter().map(|b| b.file_name().unwrap().to_string_lossy().to_string()).collect();
+    let width = names.iter().map(|name| name.len()).max().unwrap_or(0);
+    for (path, name) in benchmarks.into_iter().zip(&names) {
+        let benchmark = match Benchmark::new(path) {
+            Ok(benchmark) => benchmark,
+            Err(e) => {
+                println!("  {:width$}  {}", name, e, width = width);
+                continue;
+            }
+        };
+        let linked: Vec<&str> = registry.allocators().iter()
+            .filter(|a| binary_path(name, a.lib_name()).exists())
+            .map(|a| a.name())
+            .collect();
+        let status = if !linked.is_empty() {
+            format!("linked with {}", linked.join(", "))
+        } else if benchmark.has_objects() {
+            String::from("compiled, not linked")
+        } else {
+            String::from("not built")
+        };
+        match benchmark.get_description() {
+            Some(description) => println!("  {:width$}  {} - {}", name, status, description, width = width),
+            None => println!("  {:width$}  {}", name, status, width = width)
+        }
+    }
+}
@@ -1,34 +1,60 @@
 #![deny(unused_imports)]
 
-use std::collections::HashMap;
-use std::fs::OpenOptions;
-use std::io::BufWriter;
-use std::io::Write;
+use std::collections::BTreeMap;
 use std::iter::FromIterator;
 use std::path::{Path, PathBuf};
-use std::process::{Command, exit};
-use std::str::from_utf8;
+use std::process::exit;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::time::{Instant};
+use std::time::Duration;
 
 use clap::{App, Arg};
 
-use crate::age_checker::should_build;
-use crate::benchmark::{Benchmark, BENCHMARK_DIR, LIBRARY_DIR};
-use crate::grapher::Graph;
-
-static AVAILABLE_ALLOCATORS: [&str; 4] =
-    [
-        "libc",
-        "apfmalloc",
-        "jemalloc",
-        "lrmalloc"
-    ];
+use crate::affinity::Affinity;
+use crate::allocators::{AllocatorSpec, BuildOptions, Registry, REGISTRY_FILE};
+use crate::autotune::{autotune, AutotuneOptions, SearchRange, STRATEGIES};
+use crate::benchmark::{binary_path, Benchmark, TrialOverrides, BENCHMARK_DIR};
+use crate::bisect::{bisect, BisectOptions, Measurement};
+use crate::clean::Cleaner;
+use crate::compare::compare;
+use crate::doctor::doctor;
+use crate::error::{Failures, PlatformError};
+use crate::grapher::{draw_memory_graphs, graph_name, metric_graph_name, output_dir, topology_markers, Graph, GraphOptions, Series, DEFAULT_TITLE};
+use crate::list::list;
+use crate::metadata::{Metadata, Toolchain};
+use crate::params::{cartesian_product, parse_thread_counts, Combination, Sweep};
+use crate::regraph::regraph;
+use crate::results::{Metric, ResultFormat, ResultSet, METRICS, RESULT_FORMATS};
+use crate::runner::{SeriesRun, STDOUT_DIR};
+use crate::sampler::MEMORY_DIR;
+use crate::stats::{OutlierPolicy, OUTLIER_POLICIES};
+use crate::submodules::{pinned_commit, source_problems, update_submodules, SubmodulePolicy, SUBMODULE_POLICIES};
+use crate::worktree::{checkout, commit_range, commit_summary, resolve_commit, VariantSpec};
+
 const BINARY_DIR: &str = "./benchmarks/bin";
 const BENCHMARK_RESULTS: &str = "./benchmarks/results";
+mod affinity;
 mod benchmark;
-mod age_checker;
+mod bisect;
+mod clean;
+mod allocators;
+mod autotune;
+mod compare;
+mod counters;
+mod doctor;
+mod error;
+mod fingerprint;
 mod grapher;
+mod list;
+mod metadata;
+mod params;
+mod regraph;
+mod results;
+mod runner;
+mod rusage;
+mod sampler;
+mod stats;
+mod submodules;
+mod worktree;
 
 static DEBUG_MODE: AtomicBool = AtomicBool::new(false);
 static DYNAMIC_MODE: AtomicBool = AtomicBool::new(false);
@@ -40,28 +66,8 @@ const DYNAMIC_LIBRARY_EXTENSION: &str = ".so";
 #[cfg(target_os = "windows")]
 const DYNAMIC_LIBRARY_EXTENSION: &str = ".dylib";
 
-macro_rules! dict {
-    ($($key:expr => $value:expr),*) => {
-        {
-            let mut ret = std::collections::HashMap::new();
-            $(ret.insert($key, $value);)*
-            ret
-        }
-    };
-    ($($key:expr => $value:expr),*,) => {
-        dict![$($key => $value),*]
-    };
-}
-
 fn main() {
 
-    let benchmark_param_list = dict![
-        "t-test1"=> "10 {} 10000 10000 400",
-        "t-test2"=> "10 {} 10000 10000 400",
-    ];
-
-
-
     let matches = App::new("apfmalloc benchmarking utility")
         .author("Joshua Radin <jradin2@u.rochester.edu>")
         .version("0.1.0")
@@ -102,10 +108,9 @@ fn main() {
             Arg::with_name("threads")
                 .long("threads")
                 .short('t')
-                .about("The maximum number of threads to test")
+                .about("The thread counts to test: a maximum, a list such as 1,2,4, range:start:end[:step] or pow2:[start:]end [default: the number of CPUs the runs may be placed on]")
                 .takes_value(true)
                 .number_of_values(1)
-                .default_value("16")
         )
         .arg(
             Arg::with_name("features")
@@ -114,14 +119,443 @@ fn main() {
                 .multiple_values(true)
                 .min_values(1)
         )
+        .arg(
+            Arg::with_name("feature-sets")
+                .long("feature-sets")
+                .about("Build apfmalloc once per feature set and test each as its own series. Features in a set are joined with +, and default is the set without features")
+                .takes_value(true)
+                .min_values(1)
+                .multiple(true)
+                .conflicts_with("features")
+        )
+        .arg(
+            Arg::with_name("variant")
+                .long("variant")
+                .about("Also test another version of an allocator, as [name=]allocator@rev-or-path, where rev is a git revision of the allocator's sources")
+                .takes_value(true)
+                .min_values(1)
+                .multiple(true)
+        )
         .arg(
             Arg::with_name("dynamic")
                 .long("dynamic")
                 .about("Use dynamic libraries instead of static")
         )
+        .arg(
+            Arg::with_name("keep-going")
+                .long("keep-going")
+                .about("Record build, link, run and graph failures in the results and go on with the rest of the runs")
+        )
+        .arg(
+            Arg::with_name("trials")
+                .long("trials")
+                .about("The number of measured trials for every point, overriding the benchmark manifest")
+                .takes_value(true)
+                .number_of_values(1)
+        )
+        .arg(
+            Arg::with_name("warmup")
+                .long("warmup")
+                .about("The number of discarded warmup runs before the trials, overriding the benchmark manifest")
+                .takes_value(true)
+                .number_of_values(1)
+        )
+        .arg(
+            Arg::with_name("outliers")
+                .long("outliers")
+                .about("How outlying trials are handled, overriding the benchmark manifest")
+                .takes_value(true)
+                .number_of_values(1)
+                .possible_values(&OUTLIER_POLICIES)
+        )
+        .arg(
+            Arg::with_name("affinity")
+                .long("affinity")
+                .about("Pin the benchmarks to CPUs: not at all (none), to one hyperthread per thread filling each core first (compact), or spreading over the cores first (scatter), or to a list of CPUs such as 0-3,8")
+                .takes_value(true)
+                .number_of_values(1)
+                .default_value("none")
+        )
+        .arg(
+            Arg::with_name("counters")
+                .long("counters")
+                .about("Count the cycles, instructions, cache misses, dTLB misses and page faults of every run with perf events (Linux only)")
+        )
+        .arg(
+            Arg::with_name("memory-interval")
+                .long("memory-interval")
+                .about("Sample the RSS, anonymous memory and swap of every run at this interval, in milliseconds (Linux only)")
+                .takes_value(true)
+                .number_of_values(1)
+        )
+        .arg(
+            Arg::with_name("format")
+                .long("format")
+                .about("The formats of the structured results written next to the graphs")
+                .takes_value(true)
+                .multiple(true)
+                .use_delimiter(true)
+                .possible_values(&RESULT_FORMATS)
+                .default_values(&RESULT_FORMATS)
+        )
+        .arg(
+            Arg::with_name("graph-metrics")
+                .long("graph-metrics")
+                .about("Also draw graphs of these metrics of every trial, next to the throughput graphs")
+                .takes_value(true)
+                .multiple(true)
+                .use_delimiter(true)
+                .possible_values(&METRICS)
+        )
+        .arg(
+            Arg::with_name("sweep")
+                .long("sweep")
+                .about("Sweep a benchmark parameter: name=v1,v2,... or name=range:start:end[:step] or name=log:start:end:steps")
+                .takes_value(true)
+                .min_values(1)
+                .multiple(true)
+        )
+        .arg(
+            Arg::with_name("target-apf")
+                .long("target-apf")
+                .about("The values of TARGET_APF to run apfmalloc with, each as its own series")
+                .takes_value(true)
+                .multiple(true)
+                .use_delimiter(true)
+        )
+        .arg(
+            Arg::with_name("burst-length")
+                .long("burst-length")
+                .about("The values of BURST_LENGTH to run apfmalloc with, each as its own series")
+                .takes_value(true)
+                .multiple(true)
+                .use_delimiter(true)
+        )
+        .arg(
+            Arg::with_name("hibernation-period")
+                .long("hibernation-period")
+                .about("The values of HIBERNATION_PERIOD to run apfmalloc with, each as its own series")
+                .takes_value(true)
+                .multiple(true)
+                .use_delimiter(true)
+        )
+        .arg(
+            Arg::with_name("submodules")
+                .long("submodules")
+                .about("What may be done to the allocator submodules: nothing, and fail if they are missing or not at their pinned commits (offline), check out their pinned commits (update), or move them to their upstream branches (remote)")
+                .takes_value(true)
+                .number_of_values(1)
+                .possible_values(&SUBMODULE_POLICIES)
+                .default_value("offline")
+        )
+        .arg(
+            Arg::with_name("registry")
+                .long("registry")
+                .about("The allocator registry file declaring how each allocator is built")
+                .takes_value(true)
+                .number_of_values(1)
+                .default_value(REGISTRY_FILE)
+        )
+        .subcommand(
+            App::new("autotune")
+                .about("Searches the APF tuning variables of apfmalloc for the best throughput of a benchmark")
+                .arg(
+                    Arg::with_name("benchmark")
+                        .short('b')
+                        .long("benchmark")
+                        .takes_value(true)
+                        .number_of_values(1)
+                        .required(true)
+                        .about("The benchmark to tune for")
+                )
+                .arg(
+                    Arg::with_name("threads")
+                        .short('t')
+                        .long("threads")
+                        .takes_value(true)
+                        .number_of_values(1)
+                        .required(true)
+                        .about("The number of threads to tune for")
+                )
+                .arg(
+                    Arg::with_name("allocator")
+                        .short('a')
+                        .long("allocator")
+                        .takes_value(true)
+                        .number_of_values(1)
+                        .default_value("apfmalloc")
+                        .about("The allocator to tune, which must use the APF tuning variables")
+                )
+                .arg(
+                    Arg::with_name("target-apf")
+                        .long("target-apf")
+                        .takes_value(true)
+                        .number_of_values(1)
+                        .default_value("500:10000")
+                        .about("The range of TARGET_APF to search, as min:max")
+                )
+                .arg(
+                    Arg::with_name("burst-length")
+                        .long("burst-length")
+                        .takes_value(true)
+                        .number_of_values(1)
+                        .about("Also search BURST_LENGTH in the range min:max, after TARGET_APF")
+                )
+                .arg(
+                    Arg::with_name("strategy")
+                        .long("strategy")
+                        .takes_value(true)
+                        .number_of_values(1)
+                        .possible_values(&STRATEGIES)
+                        .default_value("golden")
+                        .about("How the ranges are searched: golden-section search, or coarse-to-fine")
+                )
+                .arg(
+                    Arg::with_name("budget")
+                        .long("budget")
+                        .takes_value(true)
+                        .number_of_values(1)
+                        .default_value("12")
+                        .about("The maximum number of settings to measure, each with the usual trials")
+                )
+        )
+        .subcommand(
+            App::new("bisect")
+                .about("Finds the first commit of an allocator that made a benchmark slower, using its local repository")
+                .arg(
+                    Arg::with_name("good")
+                        .about("A revision of the allocator without the regression")
+                        .required(true)
+                        .index(1)
+                )
+                .arg(
+                    Arg::with_name("bad")
+                        .about("A later revision of the allocator with the regression")
+                        .required(true)
+                        .index(2)
+                )
+                .arg(
+                    Arg::with_name("benchmark")
+                        .short('b')
+                        .long("benchmark")
+                        .takes_value(true)
+                        .number_of_values(1)
+                        .required(true)
+                        .about("The benchmark that regressed")
+                )
+                .arg(
+                    Arg::with_name("threads")
+                        .short('t')
+                        .long("threads")
+                        .takes_value(true)
+                        .number_of_values(1)
+                        .required(true)
+                        .about("The number of threads the benchmark regressed with")
+                )
+                .arg(
+                    Arg::with_name("threshold")
+                        .long("threshold")
+                        .takes_value(true)
+                        .number_of_values(1)
+                        .default_value("5")
+                        .about("The drop in throughput, in percent, that counts as a regression")
+                )
+                .arg(
+                    Arg::with_name("allocator")
+                        .short('a')
+                        .long("allocator")
+                        .takes_value(true)
+                        .number_of_values(1)
+                        .default_value("apfmalloc")
+                        .about("The allocator to bisect")
+                )
+                .arg(
+                    Arg::with_name("max-rounds")
+                        .long("max-rounds")
+                        .takes_value(true)
+                        .number_of_values(1)
+                        .default_value("3")
+                        .about("How many rounds of trials a commit is given while its difference isn't significant")
+                )
+        )
+        .subcommand(
+            App::new("build")
+                .about("Builds the selected allocators and benchmark binaries without running anything")
+        )
         .subcommand(
             App::new("clean")
-                .about("Cleans the allocators, forcing a remake of the allocators")
+                .about("Removes built allocators, benchmarks and results. Without options, the allocators and benchmarks are cleaned")
+                .arg(
+                    Arg::with_name("allocator")
+                        .short('a')
+                        .long("allocator")
+                        .takes_value(true)
+                        .min_values(1)
+                        .multiple(true)
+                        .about("Only clean these allocators, including their feature sets and revisions")
+                )
+                .arg(
+                    Arg::with_name("benchmarks")
+                        .long("benchmarks")
+                        .about("Clean the object files and binaries of the benchmarks")
+                )
+                .arg(
+                    Arg::with_name("results")
+                        .long("results")
+                        .about("Remove the results and graphs of previous runs")
+                )
+                .arg(
+                    Arg::with_name("older-than")
+                        .long("older-than")
+                        .takes_value(true)
+                        .number_of_values(1)
+                        .about("Only remove the results and graphs of runs older than this many days")
+                )
+                .arg(
+                    Arg::with_name("keep-builds")
+                        .long("keep-builds")
+                        .about("Only remove the libraries copied from the allocators, without running their clean commands, so that they are rebuilt incrementally")
+                )
+        )
+        .subcommand(
+            App::new("compare")
+                .about("Compares the results of two runs, exiting with 1 if the candidate regressed")
+                .arg(
+                    Arg::with_name("baseline")
+                        .about("The results directory of the baseline run")
+                        .required(true)
+                        .index(1)
+                )
+                .arg(
+                    Arg::with_name("candidate")
+                        .about("The results directory of the candidate run")
+                        .required(true)
+                        .index(2)
+                )
+                .arg(
+                    Arg::with_name("threshold")
+                        .long("threshold")
+                        .takes_value(true)
+                        .number_of_values(1)
+                        .default_value("5")
+                        .about("The drop in throughput, in percent, that counts as a regression")
+                )
+                .arg(
+                    Arg::with_name("allocator")
+                        .short('a')
+                        .long("allocator")
+                        .takes_value(true)
+                        .min_values(1)
+                        .multiple(true)
+                        .about("The allocator(s) to compare. If no allocators are specified, all are compared")
+                )
+                .arg(
+                    Arg::with_name("benchmark")
+                        .short('b')
+                        .long("benchmark")
+                        .takes_value(true)
+                        .min_values(1)
+                        .multiple(true)
+                        .about("The benchmarks to compare. If no benchmarks are specified, all are compared")
+                )
+        )
+        .subcommand(
+            App::new("doctor")
+                .about("Checks for the programs and sources needed to build the allocators and benchmarks")
+        )
+        .subcommand(
+            App::new("list")
+                .about("Lists the known allocators and benchmarks, and whether they have been built")
+        )
+        .subcommand(
+            App::new("regraph")
+                .about("Rebuilds the graphs of a previous run from its stored results, without running any benchmarks")
+                .arg(
+                    Arg::with_name("results")
+                        .about("The results directory of the previous run, such as graphs/results_<timestamp>")
+                        .required(true)
+                        .index(1)
+                )
+                .arg(
+                    Arg::with_name("allocator")
+                        .short('a')
+                        .long("allocator")
+                        .takes_value(true)
+                        .min_values(1)
+                        .multiple(true)
+                        .about("The allocator(s) to graph. If no allocators are specified, all are graphed")
+                )
+                .arg(
+                    Arg::with_name("benchmark")
+                        .short('b')
+                        .long("benchmark")
+                        .takes_value(true)
+                        .min_values(1)
+                        .multiple(true)
+                        .about("The benchmarks to graph. If no benchmarks are specified, all are graphed")
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .short('o')
+                        .long("output")
+                        .takes_value(true)
+                        .number_of_values(1)
+                        .about("The directory to write the graphs to. Defaults to a new directory in graphs")
+                )
+                .arg(
+                    Arg::with_name("title")
+                        .long("title")
+                        .takes_value(true)
+                        .number_of_values(1)
+                        .default_value(DEFAULT_TITLE)
+                        .about("The title of the graphs, where {benchmark} is replaced by the benchmark name")
+                )
+                .arg(
+                    Arg::with_name("x-label")
+                        .long("x-label")
+                        .takes_value(true)
+                        .number_of_values(1)
+                        .about("The label of the x axis")
+                )
+                .arg(
+                    Arg::with_name("y-label")
+                        .long("y-label")
+                        .takes_value(true)
+                        .number_of_values(1)
+                        .about("The label of the y axis")
+                )
+                .arg(
+                    Arg::with_name("width")
+                        .long("width")
+                        .takes_value(true)
+                        .number_of_values(1)
+                        .about("The width of the graphs, in pixels")
+                )
+                .arg(
+                    Arg::with_name("height")
+                        .long("height")
+                        .takes_value(true)
+                        .number_of_values(1)
+                        .about("The height of the graphs, in pixels")
+                )
+                .arg(
+                    Arg::with_name("no-error-bars")
+                        .long("no-error-bars")
+                        .about("Don't draw error bars and confidence bands")
+                )
+                .arg(
+                    Arg::with_name("graph-metrics")
+                        .long("graph-metrics")
+                        .about("Also draw graphs of these metrics, next to the throughput graphs")
+                        .takes_value(true)
+                        .multiple(true)
+                        .use_delimiter(true)
+                        .possible_values(&METRICS)
+                )
+        )
+        .subcommand(
+            App::new("run")
+                .about("Runs the selected benchmarks with allocators and binaries that were already built, without building anything")
         )
         .get_matches();
 
@@ -139,9 +573,9 @@ fn main() {
     }
 
     macro_rules! vprint {
-        ($($tokens:tt),+) => {
+        ($($tokens:tt)+) => {
             if verbose {
-                print!($($tokens),+)
+                print!($($tokens)+)
             }
         };
     }
@@ -149,38 +583,143 @@ fn main() {
         () => {
             vprint!("\n")
         };
-        ($($tokens:tt),*) => {
-            vprint!($($tokens),*);
+        ($($tokens:tt)*) => {
+            vprint!($($tokens)*);
             vprint!("\n")
         }
     }
 
-    if matches.subcommand_matches("clean").is_some() {
-        let cmd = Command::new("find")
-            .current_dir("./allocators/target")
-            .arg(".")
-            .args(&["-name", "*.a"])
-            .output()
-            .unwrap();
+    let registry_file = matches.value_of("registry").unwrap();
 
-        let files = String::from_utf8(cmd.stdout).unwrap()
-            .split_whitespace()
-            .map(|s| s.to_string())
-            .collect::<Vec<String>>();
-        vprintln!("Files to remove: {:?}", files);
+    if let Some(matches) = matches.subcommand_matches("regraph") {
+        // The registry is only needed for the styles of the allocators, so regraphing works without it
+        let styles = Registry::load(registry_file).map(|r| r.styles()).unwrap_or_default();
+        let mut options = GraphOptions {
+            title: matches.value_of("title").unwrap().to_string(),
+            error_bars: !matches.is_present("no-error-bars"),
+            styles,
+            ..GraphOptions::default()
+        };
+        if let Some(label) = matches.value_of("x-label") {
+            options.x_desc = label.to_string();
+        }
+        if let Some(label) = matches.value_of("y-label") {
+            options.y_desc = label.to_string();
+        }
+        if let Some(width) = matches.value_of("width") {
+            options.width = width.parse().expect("Invalid value for --width entry");
+        }
+        if let Some(height) = matches.value_of("height") {
+            options.height = height.parse().expect("Invalid value for --height entry");
+        }
 
-        // asdasd
+        let benchmarks: Option<Vec<&str>> = matches.values_of("benchmark").map(|b| b.collect());
+        let allocators: Option<Vec<&str>> = matches.values_of("allocator").map(|a| a.collect());
+        let output = matches.value_of("output").map_or_else(output_dir, PathBuf::from);
+        let metrics: Vec<Metric> = matches.values_of("graph-metrics").map_or(vec![], |m| m.map(|m| m.parse().unwrap()).collect());
+
+        if let Err(e) = regraph(
+            Path::new(matches.value_of("results").unwrap()),
+            &output,
+            benchmarks.as_deref(),
+            allocators.as_deref(),
+            &metrics,
+            &options
+        ) {
+            eprintln!("{}", e);
+            exit(2);
+        }
+        println!("Graphs written to {:?}", output);
+        return;
+    }
 
-        Command::new("rm").current_dir("./allocators/target").args(files).spawn().unwrap();
+    if let Some(matches) = matches.subcommand_matches("compare") {
+        let threshold: f64 = matches.value_of("threshold").unwrap().parse().expect("Invalid value for --threshold entry");
+        let benchmarks: Option<Vec<&str>> = matches.values_of("benchmark").map(|b| b.collect());
+        let allocators: Option<Vec<&str>> = matches.values_of("allocator").map(|a| a.collect());
+
+        match compare(
+            Path::new(matches.value_of("baseline").unwrap()),
+            Path::new(matches.value_of("candidate").unwrap()),
+            threshold,
+            benchmarks.as_deref(),
+            allocators.as_deref()
+        ) {
+            Ok(false) => return,
+            Ok(true) => {
+                eprintln!("The candidate regressed by more than {}%", threshold);
+                exit(1);
+            },
+            Err(e) => {
+                eprintln!("{}", e);
+                exit(2);
+            },
+        }
+    }
 
-        Command::new("cargo").current_dir("./allocators/apfmalloc").arg("clean").spawn().unwrap();
-        Command::new("rm").current_dir("./allocators/jemalloc/lib").arg("libjemalloc.a").spawn().unwrap();
-        let _ = Command::new("make")
-            .current_dir("./allocators/jemalloc")
-            .arg("distclean")
-            .spawn();
+    let registry = match Registry::load(registry_file) {
+        Ok(registry) => registry,
+        Err(e) => {
+            eprintln!("Could not load the allocator registry: {}", e);
+            exit(2);
+        },
+    };
 
-        Benchmark::clean_benchmarks();
+    if matches.subcommand_matches("doctor").is_some() {
+        if !doctor(&registry) {
+            exit(1);
+        }
+        return;
+    }
+
+    if matches.subcommand_matches("list").is_some() {
+        let features: Vec<&str> = matches.values_of("features").map_or(vec![], |iter| iter.collect());
+        let options = BuildOptions {
+            dynamic: DYNAMIC_MODE.load(Ordering::Acquire),
+            debug: is_debug(),
+            features: &features
+        };
+        list(&registry, &options, &Toolchain::detect());
+        return;
+    }
+
+    if let Some(matches) = matches.subcommand_matches("clean") {
+        let older_than = matches.value_of("older-than").map(|days| match days.parse::<f64>() {
+            Ok(days) if days >= 0.0 => Duration::from_secs_f64(days * 24.0 * 60.0 * 60.0),
+            _ => {
+                eprintln!("Invalid value for --older-than: {}", days);
+                exit(2);
+            }
+        });
+        let allocators: Option<Vec<&AllocatorSpec>> = matches.values_of("allocator").map(|names| names
+            .map(|name| registry.get(name).unwrap_or_else(|| {
+                eprintln!("Not a valid allocator: {}", name);
+                exit(2);
+            }))
+            .collect());
+        let benchmarks = matches.is_present("benchmarks");
+        let results = matches.is_present("results") || older_than.is_some();
+        let run_commands = !matches.is_present("keep-builds");
+
+        // Everything but the results is cleaned when nothing is chosen
+        let default = allocators.is_none() && !benchmarks && !results;
+
+        let mut cleaner = Cleaner::default();
+        if default {
+            cleaner.clean_all_allocators(registry.allocators(), run_commands);
+        }
+        for allocator in allocators.into_iter().flatten() {
+            cleaner.clean_allocator(allocator, run_commands);
+        }
+        if benchmarks || default {
+            cleaner.clean_benchmarks();
+        }
+        if results {
+            cleaner.clean_results(older_than);
+        }
+        if !cleaner.report() {
+            exit(1);
+        }
         return;
     }
 
@@ -189,15 +728,25 @@ fn main() {
         std::fs::create_dir_all(out_dir).unwrap();
     }
 
-    let allocators = matches.values_of("allocator");
+    let available_allocators = registry.names();
+    // Autotuning and bisecting select their own allocator and benchmark
+    let autotune_matches = matches.subcommand_matches("autotune");
+    let bisect_matches = matches.subcommand_matches("bisect");
+    // Building and running can be done separately, such as building once and running many times
+    let build_only = matches.subcommand_matches("build").is_some();
+    let run_only = matches.subcommand_matches("run").is_some();
+    let allocators = match autotune_matches.or(bisect_matches) {
+        Some(matches) => matches.values_of("allocator"),
+        None => matches.values_of("allocator")
+    };
     let allocators: Vec<&str> = match allocators {
         None => {
-            AVAILABLE_ALLOCATORS.to_vec()
+            available_allocators
         },
         Some(listed) => {
             let listed = listed.collect::<Vec<&str>>();
             for allocator in &listed {
-                if !AVAILABLE_ALLOCATORS.contains(allocator) {
+                if !available_allocators.contains(allocator) {
                     eprintln!("Not a valid allocator: {}", *allocator);
                     exit(2);
                 }
@@ -205,183 +754,165 @@ fn main() {
             listed
         },
     };
-
-    if !Path::new("./allocators/jemalloc/.git").exists() || !Path::new("./allocators/apfmalloc/.git").exists() || !Path::new("./allocators/lrmalloc/.git").exists() {
-        vprintln!("Initializing the submodules...");
-        Command::new("git")
-            .arg("submodule")
-            .arg("init")
-            .status()
-            .expect("Failed to initialize allocator directories");
-    }
-
-    if allocators.contains(&"jemalloc") {
-
-
-        if !Path::new("./allocators/jemalloc/Makefile").exists() ||
-            !Path::new("./allocators/apfmalloc/Cargo.toml").exists() ||
-            !Path::new("./allocators/lrmalloc/Makefile").exists() {
-            vprintln!("Updating submodules...");
-            Command::new("git")
-                .arg("submodule")
-                .arg("update")
-                .arg("--remote")
-                .status()
-                .expect("Failed to initialize allocator repos");
+    let feature_sets: Option<Vec<Vec<String>>> = matches.values_of("feature-sets").map(|sets| {
+        sets.map(|set| match set.trim() {
+            "default" => vec![],
+            set => set.split('+').map(|feature| feature.trim().to_string()).filter(|f| !f.is_empty()).collect()
+        }).collect()
+    });
+    let mut variants = vec![];
+    for variant in matches.values_of("variant").into_iter().flatten() {
+        let variant: VariantSpec = match variant.parse() {
+            Ok(variant) => variant,
+            Err(e) => {
+                eprintln!("{}", e);
+                exit(2);
+            },
+        };
+        match registry.get(&variant.allocator) {
+            Some(allocator) => variants.push((variant, allocator)),
+            None => {
+                eprintln!("Not a valid allocator: {}", variant.allocator);
+                exit(2);
+            },
         }
-
-
-        if !Path::new("./allocators/jemalloc/Makefile").exists() {
-            vprintln!("Configuring jemalloc...");
-
-            if !Path::new("./allocators/jemalloc/configure").exists() {
-                if !Path::new("./allocators/jemalloc/autogen.sh").exists() {
-                    eprintln!("Neither the Makefile, configure, or autogen.sh files exist. Can not build jemalloc");
-                    exit(5);
+    }
+    let mut allocator_specs: Vec<AllocatorSpec> = allocators.iter().map(|a| registry.get(a).unwrap().clone()).collect();
+
+    let submodule_policy: SubmodulePolicy = matches.value_of("submodules").unwrap().parse().unwrap();
+    if !run_only {
+        let selected: Vec<&AllocatorSpec> = allocator_specs.iter().collect();
+        let varied: Vec<&AllocatorSpec> = variants.iter().map(|(_, allocator)| *allocator).collect();
+        // Bisecting and variants check out their own revisions, so their allocators only need their repositories.
+        // Moving to the upstream branches leaves nothing at the pinned commits.
+        let require_pinned = bisect_matches.is_none() && submodule_policy != SubmodulePolicy::Remote;
+        let find_problems = || {
+            let mut problems = source_problems(&selected, require_pinned);
+            for problem in source_problems(&varied, false) {
+                if !problems.iter().any(|p| p.dir == problem.dir) {
+                    problems.push(problem);
                 }
+            }
+            problems
+        };
 
-
-                //let process = create_process_name_for_local("./allocators/jemalloc/autogen.sh").expect("Could not create a absolute path");
-                // vprintln!("Attempting to run {:?}", process);
-                Command::new("sh")
-                    .arg("./autogen.sh")
-                    .current_dir("./allocators/jemalloc")
-                    .status()
-                    .expect("Failed to execute process");
-            } else {
-                Command::new("./configure")
-                    .current_dir("./allocators/jemalloc")
-                    .arg("--without-export")
-                    .arg("--disable-zone-allocator")
-                    .status()
-                    .expect("Failed to run the configure command");
+        // Only submodules of the repository can be updated
+        let dirs: Vec<PathBuf> = match submodule_policy {
+            SubmodulePolicy::Offline => vec![],
+            SubmodulePolicy::Update => find_problems().into_iter().map(|p| p.dir).collect(),
+            SubmodulePolicy::Remote => selected.iter().chain(&varied).filter_map(|a| a.source_dir()).map(Path::to_path_buf).collect()
+        };
+        let mut dirs: Vec<PathBuf> = dirs.into_iter().filter(|dir| pinned_commit(dir).is_some()).collect();
+        dirs.sort();
+        dirs.dedup();
+        if !dirs.is_empty() {
+            vprintln!("Updating the submodules in {:?}", dirs);
+            if let Err(e) = update_submodules(&dirs, submodule_policy == SubmodulePolicy::Remote) {
+                eprintln!("Failed to update the submodules: {}", e);
+                exit(4);
             }
         }
 
+        let problems = find_problems();
+        if !problems.is_empty() {
+            for problem in &problems {
+                eprintln!("{}\n    Fix: {}", problem.message, problem.fix);
+            }
+            if submodule_policy == SubmodulePolicy::Offline {
+                eprintln!("The sources are never changed with --submodules offline, the default. \
+                    Run the commands above, or use --submodules update to let the platform run them");
+            }
+            exit(2);
+        }
+    }
 
-        if should_build("jemalloc") || DYNAMIC_MODE.load(Ordering::Acquire) {
-            vprintln!("Building jemalloc");
-
-            while !Path::new("./allocators/jemalloc/Makefile").exists() {}
-            let file_name = format!("libjemalloc{}", if DYNAMIC_MODE.load(Ordering::Acquire) {
-                DYNAMIC_LIBRARY_EXTENSION
-            } else {
-                ".a"
-            });
-            if !DYNAMIC_MODE.load(Ordering::Acquire) {
-                Command::new("make")
-                    .current_dir("./allocators/jemalloc")
-                    .arg("build_lib_static")
-                    .status()
-                    .unwrap();
-            } else {
-                Command::new("make")
-                    .current_dir("./allocators/jemalloc")
-                    .arg("build_lib_shared")
-                    .status()
-                    .unwrap();
+    for (variant, allocator) in &variants {
+        vprintln!("Checking out {}@{}", variant.allocator, variant.source);
+        match variant.resolve(allocator) {
+            Ok(spec) if allocator_specs.iter().any(|a| a.name() == spec.name()) => {
+                eprintln!("{} is tested more than once", spec.name());
+                exit(2);
+            },
+            Ok(spec) => allocator_specs.push(spec),
+            Err(e) => {
+                eprintln!("Could not check out {}@{}: {}", variant.allocator, variant.source, e);
+                exit(2);
+            },
+        }
+    }
+    // Allocators whose builds take features are split into one variant per feature set
+    let allocator_specs: Vec<AllocatorSpec> = allocator_specs.iter()
+        .flat_map(|spec| match &feature_sets {
+            Some(sets) if spec.has_features() => sets.iter().map(|features| spec.with_features(features)).collect(),
+            _ => vec![spec.clone()]
+        })
+        .collect();
+    if feature_sets.is_some() && !allocator_specs.iter().any(|a| a.has_features()) {
+        eprintln!("Feature sets were given, but none of the selected allocators take features");
+        exit(2);
+    }
+    let allocator_specs: Vec<&AllocatorSpec> = allocator_specs.iter().collect();
+
+    let mut apf_sweeps = BTreeMap::new();
+    for (arg, var) in [("target-apf", "TARGET_APF"), ("burst-length", "BURST_LENGTH"), ("hibernation-period", "HIBERNATION_PERIOD")] {
+        if let Some(values) = matches.values_of(arg) {
+            let values: Vec<String> = values.map(|v| v.trim().to_string()).collect();
+            if let Some(value) = values.iter().find(|v| v.parse::<usize>().is_err()) {
+                eprintln!("Invalid value for --{}: {}", arg, value);
+                exit(2);
             }
-            let mut dest_path = PathBuf::from(out_dir.to_str().unwrap());
-            dest_path.push(file_name.clone());
-            Command::new("cp")
-                .arg(format!("./allocators/jemalloc/lib/{}", file_name))
-                .arg(dest_path)
-                .status()
-                .unwrap();
-        }
-    }
-
-    if allocators.contains(&"apfmalloc") {
-
-        println!("TARGET_APF = {:?}", option_env!("TARGET_APF"));
-        let features = matches
-            .values_of("features")
-            .map_or(vec![],
-                    |iter| {
-                        let mut collected: Vec<&str> = iter.collect();
-                        collected.insert(0, "--features");
-                        collected
-                    }
-            );
+            apf_sweeps.insert(var.to_string(), values);
+        }
+    }
+    if !apf_sweeps.is_empty() && !allocator_specs.iter().any(|a| a.apf()) {
+        eprintln!("APF tuning variables were given, but none of the selected allocators use them");
+        exit(2);
+    }
+    // Without any sweeps this is a single combination that sets nothing
+    let apf_envs = cartesian_product(&BTreeMap::new(), &apf_sweeps);
+    if !apf_sweeps.is_empty() {
+        vprintln!("APF tuning combinations: {:?}", apf_envs.iter().map(|c| c.to_string()).collect::<Vec<_>>());
+    }
+    let features: Vec<&str> = matches
+        .values_of("features")
+        .map_or(vec![], |iter| iter.collect());
+    let build_options = BuildOptions {
+        dynamic: DYNAMIC_MODE.load(Ordering::Acquire),
+        debug: is_debug(),
+        features: &features
+    };
 
+    let formats: Vec<ResultFormat> = matches.values_of("format").unwrap().map(|f| f.parse().unwrap()).collect();
+    let mut result_set = ResultSet::default();
+    let mut failures = Failures::new(matches.is_present("keep-going"), &formats);
 
-        vprintln!("Creating apfmalloc");
-        let file_name = format!("libapfmalloc{}", if DYNAMIC_MODE.load(Ordering::Acquire) {
-            DYNAMIC_LIBRARY_EXTENSION
-        } else {
-            ".a"
-        });
-        if is_debug() {
-            vprintln!("Making debug version");
-            Command::new("cargo")
-                .arg("build")
-                .arg("--workspace")
-                .arg("--manifest-path")
-                .arg("allocators/apfmalloc/Cargo.toml")
-                .args(features)
-                .status()
-                .unwrap();
-            let mut dest_path = PathBuf::from(out_dir.to_str().unwrap());
-            dest_path.push(file_name.clone());
-            Command::new("cp")
-                .arg(format!("allocators/apfmalloc/target/debug/{}", file_name))
-                .arg(dest_path.to_str().unwrap())
-                .status()
-                .unwrap();
-        } else {
-            Command::new("cargo")
-                .arg("build")
-                .arg("--workspace")
-                .arg("--release")
-                .arg("--manifest-path")
-                .arg("allocators/apfmalloc/Cargo.toml")
-                .args(features)
-                .status()
-                .unwrap();
-            let mut dest_path = PathBuf::from(out_dir.to_str().unwrap());
-            dest_path.push(file_name.clone());
-            Command::new("cp")
-                .arg(format!("allocators/apfmalloc/target/release/{}", file_name))
-                .arg(dest_path.to_str().unwrap())
-                .status()
-                .unwrap();
-
-        }
-    }
-
-    if allocators.contains(&"lrmalloc") {
-        if should_build("lrmalloc") || DYNAMIC_MODE.load(Ordering::Acquire) {
-            vprintln!("Building lrmalloc");
-
-            let file_name = format!("lrmalloc{}", if DYNAMIC_MODE.load(Ordering::Acquire) {
-                DYNAMIC_LIBRARY_EXTENSION
-            } else {
-                ".a"
-            });
-            if !DYNAMIC_MODE.load(Ordering::Acquire) {
-                if !Command::new("make")
-                    .current_dir("./allocators/lrmalloc")
-                    .arg("lrmalloc.a")
-                    .status()
-                    .unwrap().success() {
-                    return;
+    let toolchain = Toolchain::detect();
+    let mut unbuilt = vec![];
+    for allocator in &allocator_specs {
+        // Bisecting builds its own revisions of the allocator
+        if allocator.lib_name().is_none() || bisect_matches.is_some() || run_only {
+            continue;
+        }
+        vprintln!("Checking whether {} has to be built", allocator.name());
+        match allocator.build_if_changed(&build_options, &toolchain) {
+            Ok(true) => {
+                vprintln!("Built {}{}", allocator.name(), if build_options.debug { " (debug)" } else { "" });
+            },
+            Ok(false) => {
+                vprintln!("{} is up to date", allocator.name());
+            },
+            Err(e) => {
+                let error = PlatformError::Build { allocator: allocator.name().to_string(), error: e };
+                if !failures.record(error, &mut result_set) {
+                    failures.stop(&result_set);
                 }
-            } else if !Command::new("make")
-                .current_dir("./allocators/lrmalloc")
-                .arg("lrmalloc.so")
-                .status()
-                .unwrap().success() {
-                return;
-            }
-            let mut dest_path = PathBuf::from(out_dir.to_str().unwrap());
-            dest_path.push(format!("lib{}", file_name));
-            Command::new("cp")
-                .arg(format!("./allocators/lrmalloc/{}", file_name))
-                .arg(dest_path)
-                .status()
-                .unwrap();
+                unbuilt.push(allocator.name());
+            },
         }
     }
+    // Allocators that failed to build are left out of the rest of the run
+    let allocator_specs: Vec<&AllocatorSpec> = allocator_specs.into_iter().filter(|a| !unbuilt.contains(&a.name())).collect();
+    let allocators: Vec<&str> = allocator_specs.iter().map(|a| a.name()).collect();
 
 
     let available_benchmarks = benchmark::get_available_benchmarks().unwrap();
@@ -393,12 +924,15 @@ fn main() {
             .map(|s| s.into_string().unwrap())
             .collect::<Vec<_>>();
 
-    let benchmarks = matches.values_of("benchmark");
-    let mut running_benchmarks: Vec<_> =
+    let benchmarks = match autotune_matches.or(bisect_matches) {
+        Some(matches) => matches.values_of("benchmark"),
+        None => matches.values_of("benchmark")
+    };
+    let running_benchmarks: Vec<PathBuf> =
         if let Some(benchmarks) = benchmarks {
             let mut out = vec![];
             for benchmark in benchmarks {
-                let benchmark = benchmark.trim_end_matches("\"");
+                let benchmark = benchmark.trim_end_matches('"');
                 if benchmark == "none" {
                     return;
                 }
@@ -408,194 +942,519 @@ fn main() {
                     eprintln!("{} is not a valid benchmark. Valid benchmarks = {:?}", benchmark, available_benchmarks);
                     exit(2);
                 }
-                let bench = Benchmark::new(PathBuf::from_iter(&[BENCHMARK_DIR, benchmark]));
-                out.push(bench);
+                out.push(PathBuf::from_iter(&[BENCHMARK_DIR, benchmark]));
             }
             out
         } else {
-            available_benchmarks.into_iter().map(|p| Benchmark::new(PathBuf::from(p))).collect()
+            available_benchmarks.into_iter().map(PathBuf::from).collect()
         };
-    running_benchmarks.sort_by(|b1, b2| b1.get_name().cmp(&b2.get_name()));
+    let mut loaded = vec![];
+    for path in running_benchmarks {
+        let name = path.file_name().unwrap().to_string_lossy().to_string();
+        match Benchmark::new(path) {
+            Ok(benchmark) => loaded.push(benchmark),
+            Err(e) => {
+                let error = PlatformError::Load { benchmark: name, error: e };
+                if !failures.record(error, &mut result_set) {
+                    failures.stop(&result_set);
+                }
+            }
+        }
+    }
+    // Benchmarks that could not be loaded are left out of the rest of the run
+    let mut running_benchmarks = loaded;
+
+    let sweeps: Vec<(String, Sweep)> = match matches.values_of("sweep") {
+        None => vec![],
+        Some(specs) => {
+            let mut out = vec![];
+            for spec in specs {
+                match Sweep::parse(spec) {
+                    Ok(sweep) => out.push(sweep),
+                    Err(e) => {
+                        eprintln!("Invalid sweep: {}", e);
+                        exit(2);
+                    },
+                }
+            }
+            out
+        },
+    };
+    for (param, _) in &sweeps {
+        if !running_benchmarks.iter().any(|b| b.has_param(param)) {
+            eprintln!("{} is not a parameter of any selected benchmark", param);
+            exit(2);
+        }
+    }
+    running_benchmarks.sort_by_key(|b| b.get_name());
 
 
     let allocator_libs: Vec<Option<String>> =
-        allocators.iter().map(|s|
-            get_allocator_lib_file(*s))
+        allocator_specs.iter().map(|a|
+            a.lib_name())
             .map(|o|
                 o.map(|s| s.to_string())
             )
             .collect();
 
-    let max_threads: usize = matches.value_of("threads").unwrap().parse().expect("Invalid value for --threads entry");
+    if build_only {
+        for benchmark in &running_benchmarks {
+            build_benchmark(benchmark, &allocator_specs, &mut failures, &mut result_set);
+        }
+        failures.finish(&result_set);
+        println!("Built {} for {}", running_benchmarks.iter().map(|b| b.get_name()).collect::<Vec<_>>().join(", "), allocators.join(", "));
+        return;
+    }
 
-    std::fs::create_dir_all(Path::new(BENCHMARK_RESULTS)).expect("Could not create benchmark result folder");
+    if run_only {
+        let mut missing = vec![];
+        for allocator in &allocator_specs {
+            let library = match allocator.lib_file(build_options.dynamic) {
+                Some(library) => library,
+                None => continue
+            };
+            if !library.exists() {
+                // Static libraries are part of the binaries, so only dynamic ones are needed to run
+                if build_options.dynamic {
+                    missing.push(library);
+                }
+            } else if allocator.fingerprint(&build_options, &toolchain).is_ok_and(|f| !f.matches(&library)) {
+                eprintln!("Warning: {} has changed since it was built, or was built with other options", allocator.name());
+            }
+        }
+        for benchmark in &running_benchmarks {
+            for library in &allocator_libs {
+                let binary = binary_path(&benchmark.get_name(), library.as_deref());
+                if !binary.exists() {
+                    missing.push(binary);
+                }
+            }
+        }
+        if !missing.is_empty() {
+            eprintln!("These have not been built: {:?}", missing);
+            eprintln!("Build them first with the build subcommand, using the same options");
+            exit(2);
+        }
+    }
 
-    for benchmark in running_benchmarks {
-        benchmark.create_object_file().unwrap();
-        let name = benchmark.get_name();
-        match benchmark.create_binaries_for(&allocator_libs) {
-            Ok(_) => {},
-            Err(e) => {
-                eprintln!("{:?}", e);
-                exit(3);
-            },
+    let trials: Option<usize> = matches.value_of("trials").map(|t| t.parse().expect("Invalid value for --trials entry"));
+    if trials == Some(0) {
+        eprintln!("--trials must be at least 1");
+        exit(2);
+    }
+    let warmup: Option<usize> = matches.value_of("warmup").map(|w| w.parse().expect("Invalid value for --warmup entry"));
+    let outliers: Option<OutlierPolicy> = matches.value_of("outliers").map(|o| o.parse().unwrap());
+    let memory_interval: Option<Duration> = matches.value_of("memory-interval")
+        .map(|i| Duration::from_millis(i.parse().expect("Invalid value for --memory-interval entry")));
+    let counters = matches.is_present("counters");
+    let affinity: Affinity = match matches.value_of("affinity").unwrap().parse() {
+        Ok(affinity) => affinity,
+        Err(e) => {
+            eprintln!("{}", e);
+            exit(2);
         }
-        if max_threads == 0 {
-            continue;
+    };
+    if let Err(e) = affinity.validate() {
+        eprintln!("{}", e);
+        exit(2);
+    }
+    if memory_interval == Some(Duration::ZERO) {
+        eprintln!("--memory-interval must be at least 1");
+        exit(2);
+    }
+    let overrides = TrialOverrides {
+        count: trials,
+        warmup,
+        outliers,
+        memory_interval,
+        counters,
+        affinity
+    };
+
+    let metadata = Metadata::collect(&allocator_specs, &features, &apf_sweeps, build_options.dynamic, build_options.debug);
+    if let Err(e) = metadata.save(output_dir()) {
+        eprintln!("Could not save the run metadata: {}", e);
+    }
+    let thread_counts = match matches.value_of("threads") {
+        Some(spec) => parse_thread_counts(spec),
+        // By default, every thread count up to the CPUs that the runs may be placed on
+        None => Ok((1..=overrides.affinity.available_cpus()).collect())
+    };
+    let thread_counts = match thread_counts {
+        Ok(thread_counts) => thread_counts,
+        Err(e) => {
+            eprintln!("Invalid value for --threads: {}", e);
+            exit(2);
         }
+    };
 
-        let mut results = HashMap::new();
-        for allocator in &allocators {
-            results.insert(*allocator, vec![]);
+    let graph_options = GraphOptions {
+        styles: registry.styles(),
+        thread_markers: topology_markers(&metadata.host),
+        ..GraphOptions::default()
+    };
+    let graph_metrics: Vec<Metric> = matches.values_of("graph-metrics")
+        .map_or(vec![], |m| m.map(|m| m.parse().unwrap()).filter(|m| *m != Metric::Throughput).collect());
+    let run_dir = output_dir();
 
-            let binary_name = format!("{}-{}", name, get_allocator_lib_file(*allocator).unwrap_or("libc"));
+    let mut folders = vec![run_dir.join(STDOUT_DIR), PathBuf::from(BENCHMARK_RESULTS)];
+    if memory_interval.is_some() {
+        folders.push(run_dir.join(MEMORY_DIR));
+    }
+    for folder in folders {
+        if let Err(e) = std::fs::create_dir_all(&folder) {
+            let e = std::io::Error::new(e.kind(), format!("could not create {:?}: {}", folder, e));
+            // Nothing can be run without the folders of the run
+            failures.record(PlatformError::IO(e), &mut result_set);
+            failures.stop(&result_set);
+        }
+    }
 
-            let mut binary_path = PathBuf::from(BINARY_DIR);
-            binary_path.push(binary_name.clone());
+    if let Some(matches) = autotune_matches {
+        // The only allocator, or the only benchmark, failed, and --keep-going recorded why
+        if allocators.is_empty() || running_benchmarks.is_empty() {
+            failures.stop(&result_set);
+        }
+        let (allocator, spec) = (allocators[0], allocator_specs[0]);
+        if !spec.apf() {
+            eprintln!("{} does not use the APF tuning variables", allocator);
+            exit(2);
+        }
+        let threads: usize = matches.value_of("threads").unwrap().parse().expect("Invalid value for --threads entry");
+        let budget: usize = matches.value_of("budget").unwrap().parse().expect("Invalid value for --budget entry");
+        if threads == 0 || budget == 0 {
+            eprintln!("--threads and --budget must be at least 1");
+            exit(2);
+        }
+        let mut ranges = vec![];
+        for (arg, var) in [("target-apf", "TARGET_APF"), ("burst-length", "BURST_LENGTH")] {
+            if let Some(spec) = matches.value_of(arg) {
+                match SearchRange::parse(var, spec) {
+                    Ok(range) => ranges.push(range),
+                    Err(e) => {
+                        eprintln!("Invalid range for --{}: {}", arg, e);
+                        exit(2);
+                    },
+                }
+            }
+        }
+        let options = AutotuneOptions {
+            strategy: matches.value_of("strategy").unwrap().parse().unwrap(),
+            budget,
+            ranges
+        };
 
-            if !binary_path.exists() {
-                panic!("Binary {} does not exist!", binary_name);
+        let benchmark = &running_benchmarks[0];
+        let name = benchmark.get_name();
+        if build_benchmark(benchmark, &allocator_specs, &mut failures, &mut result_set).is_empty() {
+            failures.stop(&result_set);
+        }
+        let combination = match benchmark.get_combinations(&[]) {
+            Ok(mut combinations) => combinations.remove(0),
+            Err(e) => {
+                eprintln!("{}", e);
+                exit(2);
+            },
+        };
+        let settings = benchmark.get_trial_settings().with_overrides(&overrides);
+        vprintln!("{}", settings);
+
+        let result = autotune(&name, allocator, threads, options, |env| {
+            let sweeps = env.iter().map(|(var, value)| (var.clone(), vec![value.clone()])).collect();
+            let env = cartesian_product(&BTreeMap::new(), &sweeps).remove(0);
+            let mut run = SeriesRun::new(&name, allocator, spec, &env, &settings).unwrap_or_else(|e| {
+                eprintln!("{}", e);
+                exit(e.exit_code());
+            });
+            let args = benchmark.get_args(&combination, threads);
+            let (record, kept) = run.run_point(&name, &combination, threads, args, &settings, &run_dir);
+            result_set.runs.push(record);
+            if let Err(e) = result_set.save(output_dir(), &formats) {
+                if !failures.record(PlatformError::IO(e), &mut result_set) {
+                    failures.stop(&result_set);
+                }
             }
+            kept.ok()
+        });
 
+        result.report();
+        if let Err(e) = result.save(output_dir()) {
+            if !failures.record(PlatformError::IO(e), &mut result_set) {
+                failures.stop(&result_set);
+            }
+        }
+        if let Err(e) = result.make_graphs(&output_dir(), &graph_options) {
+            eprintln!("Failed to draw the autotuning graphs: {}", e);
+            exit(6);
+        }
+        failures.finish(&result_set);
+        return;
+    }
 
+    if let Some(matches) = bisect_matches {
+        if running_benchmarks.is_empty() {
+            failures.stop(&result_set);
+        }
+        let allocator = allocator_specs[0];
+        let threads: usize = matches.value_of("threads").unwrap().parse().expect("Invalid value for --threads entry");
+        let options = BisectOptions {
+            threshold: matches.value_of("threshold").unwrap().parse().expect("Invalid value for --threshold entry"),
+            max_rounds: matches.value_of("max-rounds").unwrap().parse().expect("Invalid value for --max-rounds entry"),
+            outliers: outliers.unwrap_or_default()
+        };
+        if threads == 0 || options.max_rounds == 0 {
+            eprintln!("--threads and --max-rounds must be at least 1");
+            exit(2);
+        }
+        let (good, bad) = match (
+            resolve_commit(allocator, matches.value_of("good").unwrap()),
+            resolve_commit(allocator, matches.value_of("bad").unwrap())
+        ) {
+            (Ok(good), Ok(bad)) => (good, bad),
+            (Err(e), _) | (_, Err(e)) => {
+                eprintln!("{}", e);
+                exit(2);
+            },
+        };
+        let commits = match commit_range(allocator, &good, &bad) {
+            Ok(commits) => commits,
+            Err(e) => {
+                eprintln!("{}", e);
+                exit(2);
+            },
+        };
+        println!("Bisecting {} commits of {}", commits.len(), allocator.name());
 
-            let output_file_name = format!("{}.txt", binary_name);
-            let output_file_path = PathBuf::from_iter(&[BENCHMARK_RESULTS, output_file_name.as_str()]);
-            let output_file =
-                OpenOptions::new()
-                    .create(true)
-                    .write(true)
-                    .open(output_file_path)
-                    .expect(format!("Failed to create result file for {}", binary_name).as_str());
-
-            let mut writer = BufWriter::new(output_file);
-
-
-            for thread_count in 1..=max_threads {
-                let params = benchmark_param_list[&*name];
-                let args =
-                    params.replace("{}", & *thread_count.to_string())
-                        .split_whitespace()
-                        .map(|s| s.to_string())
-                        .collect::<Vec<String>>();
-
-                println!("Running {} with {} threads", binary_path.as_path().file_name().unwrap().to_str().unwrap(), thread_count);
-                writeln!(&mut writer, "-------------- [START] {} with {} threads --------------",
-                         binary_name,
-                         thread_count
-                ).unwrap();
-
-                let mut sum_throughput = 0.0;
-                const NUM_TRIALS: usize = 3;
-                for i in 0..NUM_TRIALS {
-                    writeln!(
-                        &mut writer,
-                        "---- ))Start Iteration {} ----",
-                        i
-                    ).unwrap();
-
-
-                    let mut command = Command::new(binary_path.to_str().unwrap());
-                    command
-                        .args(args.clone());
-                    if DYNAMIC_MODE.load(Ordering::Acquire) {
-                        if let Some(allocator) = get_allocator_lib_file(*allocator) {
-                            let path = {
-                                let mut path = PathBuf::from(LIBRARY_DIR);
-                                path.push(format!("lib{}{}", allocator, DYNAMIC_LIBRARY_EXTENSION));
-                                path
-                            };
-
-                            let path = path.canonicalize().unwrap_or_else(|_| panic!("Could not get canonical path for the dynamic library at {:?}", path));
-
-
-                            #[cfg(target_os = "linux")]
-                                command.env("LD_PRELOAD", path);
-                            #[cfg(target_os = "macos")]
-                                command.env("DYLD_INSERT_LIBRARIES", path);
+        let benchmark = &running_benchmarks[0];
+        let name = benchmark.get_name();
+        if let Err(e) = benchmark.create_object_file() {
+            eprintln!("Failed to compile {}: {}", name, e);
+            exit(3);
+        }
+        let combination = match benchmark.get_combinations(&[]) {
+            Ok(mut combinations) => combinations.remove(0),
+            Err(e) => {
+                eprintln!("{}", e);
+                exit(2);
+            },
+        };
+        // Bisecting uses the same outlier policy to compare every commit
+        let overrides = TrialOverrides { outliers: Some(options.outliers), ..overrides };
+        let settings = benchmark.get_trial_settings().with_overrides(&overrides);
+        vprintln!("{}", settings);
+
+        // Checks out, builds and links a commit, the first time it is measured
+        let prepare = |commit: &str| -> Result<SeriesRun, String> {
+            let worktree = checkout(allocator, commit).map_err(|e| e.to_string())?;
+            let short = &commit[..12];
+            let spec = allocator.with_source(
+                &format!("{}@{}", allocator.name(), short),
+                worktree,
+                &format!("{}-{}", allocator.lib_name().unwrap(), short),
+                Some(commit.to_string())
+            );
+            spec.build_if_changed(&build_options, &toolchain).map_err(|e| e.to_string())?;
+            benchmark.create_binaries_for(&vec![spec.lib_name().map(String::from)]).map_err(|e| e.to_string())?;
+            SeriesRun::new(&name, spec.name(), &spec, &Combination::default(), &settings).map_err(|e| e.to_string())
+        };
+        let mut runs = BTreeMap::new();
+        let result = bisect(&good, &commits, &options, |commit| {
+            match runs.entry(commit.to_string()).or_insert_with(|| prepare(commit)) {
+                Ok(run) => {
+                    let args = benchmark.get_args(&combination, threads);
+                    let (record, kept) = run.run_point(&name, &combination, threads, args, &settings, &run_dir);
+                    let throughputs = record.throughputs();
+                    result_set.runs.push(record);
+                    if let Err(e) = result_set.save(output_dir(), &formats) {
+                        if !failures.record(PlatformError::IO(e), &mut result_set) {
+                            failures.stop(&result_set);
                         }
                     }
-                    let start = Instant::now();
-                    let output = command.output().unwrap();
-
-
+                    match kept {
+                        Ok(_) => Measurement::Throughputs(throughputs),
+                        Err(_) => Measurement::Crashed
+                    }
+                },
+                Err(e) => Measurement::Unbuildable(e.clone())
+            }
+        });
 
-                    let duration = start.elapsed();
-                    if !output.status.success() {
-                        eprintln!("Program exited with code {}", output.status);
-                        writeln!(
-                            &mut writer,
-                            "PROGRAM CRASHED\n-------------- [END] --------------"
-                        ).unwrap();
-                        return;
+        match result {
+            Ok(bisection) => {
+                if let Err(e) = bisection.save(output_dir()) {
+                    if !failures.record(PlatformError::IO(e), &mut result_set) {
+                        failures.stop(&result_set);
                     }
-                    let output = from_utf8(&*output.stdout).expect("Output not in utf-8");
-                    println!("{}", output);
-                    writeln!(
-                        &mut writer,
-                        "{}",
-                        output
-                    ).unwrap();
-
-                    let throughput = 1.0 / duration.as_secs_f64();
-                    writeln!(
-                        &mut writer,
-                        "Throughput: {}",
-                        throughput
-                    ).unwrap();
-                    sum_throughput += throughput;
                 }
-                let average = sum_throughput/(NUM_TRIALS as f64);
-                writeln!(
-                    &mut writer,
-                    "#### Average Throughput: {} ####",
-                    average
-                ).unwrap();
-                results.get_mut(allocator).unwrap().push(average);
-                writeln!(
-                    &mut writer,
-                    "-------------- [END] --------------"
-                ).unwrap();
-            }
+                println!("First regressed commit: {}", commit_summary(allocator, &bisection.first_bad));
+                if !bisection.skipped.is_empty() {
+                    println!("These commits before it could not be built, so any of them may have regressed first:");
+                    for commit in &bisection.skipped {
+                        println!("    {}", commit_summary(allocator, commit));
+                    }
+                }
+            },
+            Err(e) => {
+                eprintln!("{}", e);
+                exit(1);
+            },
+        }
+        failures.finish(&result_set);
+        return;
+    }
 
-            writer.flush().unwrap();
+    for benchmark in running_benchmarks {
+        let linked = if run_only {
+            allocators.clone()
+        } else {
+            build_benchmark(&benchmark, &allocator_specs, &mut failures, &mut result_set)
+        };
+        let name = benchmark.get_name();
+        if let Some(description) = benchmark.get_description() {
+            vprintln!("{}: {}", name, description);
+        }
+        if thread_counts.is_empty() {
+            continue;
         }
-        let graph = Graph::new(name, results, max_threads);
-        match graph.make_graph() {
-            Ok(_) => {},
+
+        let combinations = match benchmark.get_combinations(&sweeps) {
+            Ok(combinations) => combinations,
             Err(e) => {
-                panic!("{:?}", e);
+                eprintln!("{}", e);
+                exit(2);
             },
+        };
+
+        let settings = benchmark.get_trial_settings().with_overrides(&overrides);
+        vprintln!("{}", settings);
+
+        let mut runs = vec![];
+        for (allocator, spec) in allocators.iter().zip(&allocator_specs) {
+            if !linked.contains(allocator) {
+                continue;
+            }
+            // Allocators that use the APF tuning variables are run once for every combination of them
+            let no_env = [Combination::default()];
+            let envs: &[Combination] = if spec.apf() { &apf_envs } else { &no_env };
+            for env in envs {
+                match SeriesRun::new(&name, allocator, spec, env, &settings) {
+                    Ok(run) => runs.push(run),
+                    Err(e) => {
+                        if !failures.record(e, &mut result_set) {
+                            failures.stop(&result_set);
+                        }
+                    },
+                }
+            }
+        }
+
+        for combination in &combinations {
+            let mut results = BTreeMap::new();
+            let mut failed: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+            let mut stop = false;
+            let first_record = result_set.runs.len();
+            'series: for run in &mut runs {
+                results.insert(run.series.clone(), vec![]);
+
+                for &thread_count in &thread_counts {
+                    let args = benchmark.get_args(combination, thread_count);
+                    let (record, kept) = run.run_point(&name, combination, thread_count, args, &settings, &run_dir);
+                    result_set.runs.push(record);
+                    if let Err(e) = result_set.save(output_dir(), &formats) {
+                        if !failures.record(PlatformError::IO(e), &mut result_set) {
+                            stop = true;
+                        }
+                    }
+                    match kept {
+                        Ok(kept) => results.get_mut(&run.series).unwrap().push((thread_count, kept)),
+                        Err(e) => {
+                            failed.entry(run.series.clone()).or_default().push(thread_count);
+                            if !failures.record(e, &mut result_set) {
+                                stop = true;
+                            }
+                        },
+                    }
+                    if stop {
+                        break 'series;
+                    }
+                }
+            }
+
+            // The graph also shows what was measured before a failure stopped the run
+            results.retain(|_, series: &mut Vec<_>| !series.is_empty());
+            let graph_name = graph_name(&name, &combination.to_string());
+            let graph = Graph::new(graph_name.clone(), results, &graph_options).with_failures(failed.clone());
+            let mut drawn = graph.make_graph(&output_dir()).map_err(|e| (graph_name.clone(), e.to_string()));
+            for metric in &graph_metrics {
+                let mut results: BTreeMap<String, Series> = BTreeMap::new();
+                for record in result_set.runs[first_record..].iter().filter(|r| r.failure.is_none()) {
+                    if let Some(samples) = record.samples(*metric) {
+                        results.entry(record.allocator.clone()).or_default().push((record.threads, samples));
+                    }
+                }
+                let name = metric_graph_name(&graph_name, *metric);
+                let options = graph_options.for_metric(&graph_name, *metric);
+                let graph = Graph::new(name.clone(), results, &options).with_failures(failed.clone());
+                drawn = drawn.and(graph.make_graph(&output_dir()).map_err(|e| (name, e.to_string())));
+            }
+            if memory_interval.is_some() {
+                let records = result_set.runs[first_record..].iter();
+                drawn = drawn.and(
+                    draw_memory_graphs(&graph_name, records, &run_dir, &graph_options, &run_dir)
+                        .map_err(|e| (format!("{} memory", graph_name), e.to_string()))
+                );
+            }
+            if let Err((graph, error)) = drawn {
+                let error = PlatformError::Graph { graph, error };
+                if !failures.record(error, &mut result_set) {
+                    stop = true;
+                }
+            }
+            if stop {
+                failures.stop(&result_set);
+            }
         }
 
     }
 
+    failures.finish(&result_set);
 
 
 }
 
 
 
-fn is_debug() -> bool {
-    DEBUG_MODE.load(Ordering::Acquire)
-}
-
-fn get_allocator_lib_file(allocator_name: &str) -> Option<&str> {
-    match allocator_name {
-        "libc" => {
-            None
-        },
-        "apfmalloc" => {
-            Some("apfmalloc")
-        },
-        "jemalloc" => {
-            Some("jemalloc")
-        },
-        "lrmalloc" => {
-            Some("lrmalloc")
+/// Compiles a benchmark and links it with every allocator. Returns the allocators it could be linked with;
+/// failures stop the platform unless `--keep-going` is given.
+fn build_benchmark<'a>(
+    benchmark: &Benchmark,
+    allocators: &[&'a AllocatorSpec],
+    failures: &mut Failures,
+    result_set: &mut ResultSet
+) -> Vec<&'a str> {
+    let name = benchmark.get_name();
+    if let Err(e) = benchmark.create_object_file() {
+        let error = PlatformError::Link { benchmark: name, allocator: None, error: e };
+        if !failures.record(error, result_set) {
+            failures.stop(result_set);
         }
-        a => {
-            panic!("{} is not a registered allocator!", a)
+        return vec![];
+    }
+    let mut linked = vec![];
+    for allocator in allocators {
+        match benchmark.create_binaries_for(&vec![allocator.lib_name().map(String::from)]) {
+            Ok(()) => linked.push(allocator.name()),
+            Err(e) => {
+                let error = PlatformError::Link { benchmark: name.clone(), allocator: Some(allocator.name().to_string()), error: e };
+                if !failures.record(error, result_set) {
+                    failures.stop(result_set);
+                }
+            },
         }
     }
+    linked
+}
+
+fn is_debug() -> bool {
+    DEBUG_MODE.load(Ordering::Acquire)
 }
@@ -0,0 +1,182 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+
+use crate::affinity::allowed_cpu_counts;
+use crate::allocators::AllocatorSpec;
+use crate::submodules::pinned_commit;
+
+/// The name of the metadata file inside of a results directory
+pub const METADATA_FILE: &str = "metadata.json";
+
+/// The environment variables that configure the APF tuner of apfmalloc
+pub const APF_ENV_VARS: [&str; 3] = ["TARGET_APF", "BURST_LENGTH", "HIBERNATION_PERIOD"];
+
+/// A record of everything that produced a results directory
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Metadata {
+    pub timestamp: String,
+    /// The full command line the platform was run with
+    pub command_line: Vec<String>,
+    pub host: HostInfo,
+    pub toolchain: Toolchain,
+    pub allocators: Vec<AllocatorRevision>,
+    pub apfmalloc_features: Vec<String>,
+    /// The APF tuning variables set in the environment of the benchmarks
+    pub apf_env: BTreeMap<String, String>,
+    /// The values of the APF tuning variables that were swept over
+    pub apf_sweeps: BTreeMap<String, Vec<String>>,
+    pub dynamic: bool,
+    pub debug: bool
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HostInfo {
+    pub cpu_model: Option<String>,
+    pub logical_cpus: usize,
+    pub physical_cores: usize,
+    pub threads_per_core: usize,
+    pub kernel: Option<String>
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Toolchain {
+    pub cc: Option<String>,
+    pub rustc: Option<String>
+}
+
+/// The revision of the sources an allocator was built from
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AllocatorRevision {
+    pub name: String,
+    pub source_dir: Option<PathBuf>,
+    pub commit: Option<String>,
+    /// The commit that the repository pins the sources to, if they are a submodule
+    #[serde(default)]
+    pub pinned: Option<String>,
+    /// Whether the sources had uncommitted changes
+    pub dirty: bool
+}
+
+/// Runs a command and returns the first line of its output, if it succeeded
+fn command_output(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let text = String::from_utf8_lossy(&output.stdout);
+    text.lines().next().map(|line| line.trim().to_string())
+}
+
+impl HostInfo {
+
+    /// Reads the CPU information of this machine. The CPUs and cores are only those the platform may run on,
+    /// so they follow taskset and cgroup limits
+    pub fn detect() -> Self {
+        let (logical_cpus, mut physical_cores) = allowed_cpu_counts();
+
+        let cpu_model = std::fs::read_to_string("/proc/cpuinfo").ok()
+            .and_then(|cpuinfo| {
+                cpuinfo.lines()
+                    .filter_map(|line| line.split_once(':'))
+                    .find(|(key, _)| key.trim() == "model name")
+                    .map(|(_, value)| value.trim().to_string())
+            })
+            .or_else(|| command_output("sysctl", &["-n", "machdep.cpu.brand_string"]));
+
+        // Without the topology in sysfs, only the machine as a whole is known
+        if !cfg!(target_os = "linux") {
+            physical_cores = command_output("sysctl", &["-n", "hw.physicalcpu"])
+                .and_then(|n| n.parse().ok())
+                .unwrap_or(physical_cores);
+        }
+
+        Self {
+            cpu_model,
+            logical_cpus,
+            physical_cores,
+            threads_per_core: (logical_cpus / physical_cores.max(1)).max(1),
+            kernel: command_output("uname", &["-srm"])
+        }
+    }
+}
+
+impl Toolchain {
+    pub fn detect() -> Self {
+        Self {
+            cc: command_output("cc", &["--version"]),
+            rustc: command_output("rustc", &["--version"])
+        }
+    }
+}
+
+impl AllocatorRevision {
+
+    /// Finds the git commit that the sources of the allocator are checked out at
+    pub fn detect(allocator: &AllocatorSpec) -> Self {
+        let source_dir = allocator.source_dir();
+        let (commit, dirty) = match source_dir {
+            Some(dir) if dir.exists() => {
+                let dir = dir.to_str().unwrap();
+                let commit = command_output("git", &["-C", dir, "rev-parse", "HEAD"]);
+                let dirty = Command::new("git")
+                    .args(["-C", dir, "status", "--porcelain"])
+                    .output()
+                    .is_ok_and(|o| o.status.success() && !o.stdout.is_empty());
+                (commit, dirty)
+            },
+            _ => (None, false)
+        };
+        Self {
+            name: allocator.name().to_string(),
+            source_dir: source_dir.map(Path::to_path_buf),
+            commit,
+            pinned: source_dir.and_then(pinned_commit),
+            dirty
+        }
+    }
+}
+
+impl Metadata {
+
+    pub fn collect(
+        allocators: &[&AllocatorSpec],
+        features: &[&str],
+        apf_sweeps: &BTreeMap<String, Vec<String>>,
+        dynamic: bool,
+        debug: bool
+    ) -> Self {
+        let apf_env = APF_ENV_VARS.iter()
+            .filter_map(|var| std::env::var(var).ok().map(|value| (var.to_string(), value)))
+            .collect();
+
+        Self {
+            timestamp: Local::now().to_rfc3339(),
+            command_line: std::env::args().collect(),
+            host: HostInfo::detect(),
+            toolchain: Toolchain::detect(),
+            allocators: allocators.iter().map(|a| AllocatorRevision::detect(a)).collect(),
+            apfmalloc_features: features.iter().map(|f| f.to_string()).collect(),
+            apf_env,
+            apf_sweeps: apf_sweeps.clone(),
+            dynamic,
+            debug
+        }
+    }
+
+    /// Loads the metadata stored in a results directory
+    pub fn load<P: AsRef<Path>>(dir: P) -> Result<Self, std::io::Error> {
+        let file = File::open(dir.as_ref().join(METADATA_FILE))?;
+        serde_json::from_reader(file).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+    }
+
+    /// Writes the metadata into the given results directory
+    pub fn save<P: AsRef<Path>>(&self, dir: P) -> Result<(), std::io::Error> {
+        let file = File::create(dir.as_ref().join(METADATA_FILE))?;
+        serde_json::to_writer_pretty(file, self).map_err(std::io::Error::other)
+    }
+}
@@ -0,0 +1,295 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+
+use serde::Deserialize;
+
+/// The values a benchmark parameter takes during a sweep
+///
+/// In a benchmark manifest, a sweep is either a list of values (`size = [100, 1000]`), a linear
+/// range (`size = { range = [100, 1000], step = 100 }`), or a logarithmic range
+/// (`bins = { log_range = [10, 1000], steps = 3 }`). Both ranges are inclusive.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum Sweep {
+    Values(Vec<toml::Value>),
+    Range {
+        range: [i64; 2],
+        #[serde(default = "Sweep::default_step")]
+        step: i64
+    },
+    LogRange {
+        log_range: [f64; 2],
+        steps: usize
+    }
+}
+
+/// Converts a parameter value from a manifest into the text passed on the command line
+pub fn value_to_string(value: &toml::Value) -> Result<String, String> {
+    match value {
+        toml::Value::String(s) => Ok(s.clone()),
+        toml::Value::Integer(i) => Ok(i.to_string()),
+        toml::Value::Float(f) => Ok(f.to_string()),
+        other => Err(format!("{} is not a valid parameter value", other))
+    }
+}
+
+impl Sweep {
+    fn default_step() -> i64 {
+        1
+    }
+
+    /// Parses a sweep given on the command line, in the form `name=v1,v2,...`,
+    /// `name=range:start:end[:step]` or `name=log:start:end:steps`
+    pub fn parse(spec: &str) -> Result<(String, Sweep), String> {
+        let (name, values) = match spec.find('=') {
+            Some(index) => (&spec[..index], &spec[index + 1..]),
+            None => return Err(format!("{} is not in the form name=values", spec))
+        };
+        if name.is_empty() || values.is_empty() {
+            return Err(format!("{} is not in the form name=values", spec));
+        }
+
+        let parts: Vec<&str> = values.split(':').collect();
+        let int = |s: &str| s.parse::<i64>().map_err(|_| format!("{} is not an integer in {}", s, spec));
+        let sweep = match parts[0] {
+            "range" if parts.len() == 3 || parts.len() == 4 => {
+                Sweep::Range {
+                    range: [int(parts[1])?, int(parts[2])?],
+                    step: if parts.len() == 4 { int(parts[3])? } else { 1 }
+                }
+            },
+            "log" if parts.len() == 4 => {
+                let float = |s: &str| s.parse::<f64>().map_err(|_| format!("{} is not a number in {}", s, spec));
+                let steps = match parts[3].parse::<usize>() {
+                    Ok(0) => return Err(format!("{} must have at least 1 step", spec)),
+                    Ok(steps) => steps,
+                    Err(_) => return Err(format!("{} is not a number of steps in {}", parts[3], spec))
+                };
+                Sweep::LogRange {
+                    log_range: [float(parts[1])?, float(parts[2])?],
+                    steps
+                }
+            },
+            "range" | "log" => return Err(format!("{} has the wrong number of range arguments", spec)),
+            _ => {
+                let values: Vec<&str> = values.split(',').map(str::trim).collect();
+                if values.iter().any(|v| v.is_empty()) {
+                    return Err(format!("{} has an empty value", spec));
+                }
+                Sweep::Values(values.into_iter().map(|v| toml::Value::String(v.to_string())).collect())
+            }
+        };
+        Ok((name.to_string(), sweep))
+    }
+
+    /// The list of values this sweep produces, in order
+    pub fn values(&self) -> Result<Vec<String>, String> {
+        let values = match self {
+            Sweep::Values(values) => {
+                values.iter().map(value_to_string).collect::<Result<Vec<_>, _>>()?
+            },
+            Sweep::Range { range: [start, end], step } => {
+                if *step <= 0 || start > end {
+                    return Err(format!("range [{}, {}] with step {} is empty", start, end, step));
+                }
+                (*start..=*end).step_by(*step as usize).map(|v| v.to_string()).collect()
+            },
+            Sweep::LogRange { log_range: [start, end], steps } => {
+                if *start <= 0.0 || start > end || *steps == 0 {
+                    return Err(format!("log range [{}, {}] with {} steps is empty", start, end, steps));
+                }
+                let mut out: Vec<String> = vec![];
+                for i in 0..*steps {
+                    let fraction = if *steps == 1 { 0.0 } else { i as f64 / (*steps - 1) as f64 };
+                    let value = (start * (end / start).powf(fraction)).round() as i64;
+                    let value = value.to_string();
+                    if !out.contains(&value) {
+                        out.push(value);
+                    }
+                }
+                out
+            }
+        };
+        if values.is_empty() {
+            return Err(String::from("a sweep must have at least one value"));
+        }
+        Ok(values)
+    }
+}
+
+/// One assignment of values to every parameter of a benchmark
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Combination {
+    values: BTreeMap<String, String>,
+    swept: Vec<String>
+}
+
+impl Combination {
+
+    /// The value of every parameter
+    pub fn values(&self) -> &BTreeMap<String, String> {
+        &self.values
+    }
+
+    /// The parameters that differ between combinations, with their values
+    pub fn swept(&self) -> Vec<(&str, &str)> {
+        self.swept.iter().map(|name| (name.as_str(), self.values[name].as_str())).collect()
+    }
+}
+
+impl Display for Combination {
+
+    /// Shows only the swept parameters, as `name=value,name=value`
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let swept: Vec<String> = self.swept().into_iter().map(|(name, value)| format!("{}={}", name, value)).collect();
+        write!(f, "{}", swept.join(","))
+    }
+}
+
+/// Creates every combination of the swept values, using the default value for parameters that are not swept
+pub fn cartesian_product(defaults: &BTreeMap<String, String>, sweeps: &BTreeMap<String, Vec<String>>) -> Vec<Combination> {
+    let swept: Vec<String> = sweeps.keys().cloned().collect();
+    let mut output = vec![Combination {
+        values: defaults.clone(),
+        swept: swept.clone()
+    }];
+
+    for (name, values) in sweeps {
+        let mut next = vec![];
+        for combination in &output {
+            for value in values {
+                let mut combination = combination.clone();
+                combination.values.insert(name.clone(), value.clone());
+                next.push(combination);
+            }
+        }
+        output = next;
+    }
+
+    output
+}
+
+/// Parses the thread counts to run the benchmarks with: `n` for every count from 1 to n, a list such as
+/// `1,2,4`, `range:start:end[:step]`, or `pow2:[start:]end` for the powers of two from start to end
+pub fn parse_thread_counts(spec: &str) -> Result<Vec<usize>, String> {
+    let count = |s: &str| s.trim().parse::<usize>().map_err(|_| format!("{} is not a thread count in {}", s, spec));
+    let parts: Vec<&str> = spec.split(':').collect();
+    let mut counts: Vec<usize> = match parts[0] {
+        "range" if parts.len() == 3 || parts.len() == 4 => {
+            let (start, end) = (count(parts[1])?, count(parts[2])?);
+            let step = if parts.len() == 4 { count(parts[3])? } else { 1 };
+            if step == 0 || start > end {
+                return Err(format!("range [{}, {}] with step {} is empty", start, end, step));
+            }
+            (start..=end).step_by(step).collect()
+        },
+        "pow2" if parts.len() == 2 || parts.len() == 3 => {
+            let (start, end) = if parts.len() == 3 { (count(parts[1])?, count(parts[2])?) } else { (1, count(parts[1])?) };
+            let powers: Vec<usize> = (0..usize::BITS)
+                .map(|exponent| 1usize << exponent)
+                .filter(|power| (start..=end).contains(power))
+                .collect();
+            if powers.is_empty() {
+                return Err(format!("there are no powers of two from {} to {}", start, end));
+            }
+            powers
+        },
+        "range" | "pow2" => return Err(format!("{} has the wrong number of range arguments", spec)),
+        _ if spec.contains(',') => spec.split(',').map(count).collect::<Result<_, _>>()?,
+        // A single number is the largest thread count, and 0 runs nothing
+        _ => (1..=count(spec)?).collect()
+    };
+    if counts.contains(&0) {
+        return Err(format!("{} has a thread count of 0", spec));
+    }
+    counts.sort_unstable();
+    counts.dedup();
+    Ok(counts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_values(spec: &str) -> Result<(String, Vec<String>), String> {
+        let (name, sweep) = Sweep::parse(spec)?;
+        Ok((name, sweep.values()?))
+    }
+
+    #[test]
+    fn sweep_lists_are_trimmed() {
+        assert_eq!(parse_values("size= 8, 16 ,32"), Ok((String::from("size"), vec![String::from("8"), String::from("16"), String::from("32")])));
+    }
+
+    #[test]
+    fn sweep_lists_reject_empty_values() {
+        assert!(Sweep::parse("size=8,,16").is_err());
+        assert!(Sweep::parse("size=8,").is_err());
+        assert!(Sweep::parse("size= ").is_err());
+        assert!(Sweep::parse("size=").is_err());
+        assert!(Sweep::parse("=8").is_err());
+        assert!(Sweep::parse("size").is_err());
+    }
+
+    #[test]
+    fn sweep_ranges() {
+        assert_eq!(parse_values("n=range:1:4").unwrap().1, ["1", "2", "3", "4"]);
+        assert_eq!(parse_values("n=range:0:10:5").unwrap().1, ["0", "5", "10"]);
+        assert!(parse_values("n=range:4:1").is_err());
+        assert!(parse_values("n=range:1:4:0").is_err());
+        assert!(parse_values("n=range:1").is_err());
+        assert!(parse_values("n=range:a:4").is_err());
+    }
+
+    #[test]
+    fn sweep_log_ranges() {
+        assert_eq!(parse_values("n=log:10:1000:3").unwrap().1, ["10", "100", "1000"]);
+        assert_eq!(parse_values("n=log:10:1000:1").unwrap().1, ["10"]);
+        // Steps that round to the same value are only run once
+        assert_eq!(parse_values("n=log:1:2:4").unwrap().1, ["1", "2"]);
+        assert!(parse_values("n=log:10:1000:0").is_err());
+        assert!(parse_values("n=log:0:1000:3").is_err());
+        assert!(parse_values("n=log:10:1000").is_err());
+    }
+
+    #[test]
+    fn cartesian_product_of_sweeps() {
+        let defaults: BTreeMap<String, String> = [("a", "0"), ("b", "0"), ("c", "7")].iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        let mut sweeps = BTreeMap::new();
+        sweeps.insert(String::from("a"), vec![String::from("1"), String::from("2")]);
+        sweeps.insert(String::from("b"), vec![String::from("3"), String::from("4")]);
+        let combinations = cartesian_product(&defaults, &sweeps);
+        let labels: Vec<String> = combinations.iter().map(|c| c.to_string()).collect();
+        assert_eq!(labels, ["a=1,b=3", "a=1,b=4", "a=2,b=3", "a=2,b=4"]);
+        assert!(combinations.iter().all(|c| c.values()["c"] == "7"));
+    }
+
+    #[test]
+    fn thread_counts() {
+        assert_eq!(parse_thread_counts("4"), Ok(vec![1, 2, 3, 4]));
+        assert_eq!(parse_thread_counts("8, 2,4,2"), Ok(vec![2, 4, 8]));
+        assert_eq!(parse_thread_counts("range:2:8:3"), Ok(vec![2, 5, 8]));
+        assert_eq!(parse_thread_counts("0"), Ok(vec![]));
+        assert!(parse_thread_counts("1,2,").is_err());
+        assert!(parse_thread_counts("1,,2").is_err());
+        assert!(parse_thread_counts("0,1").is_err());
+        assert!(parse_thread_counts("").is_err());
+        assert!(parse_thread_counts("two").is_err());
+        assert!(parse_thread_counts("range:8:2").is_err());
+        assert!(parse_thread_counts("range:1:8:0").is_err());
+        assert!(parse_thread_counts("range:1").is_err());
+    }
+
+    #[test]
+    fn pow2_thread_counts() {
+        assert_eq!(parse_thread_counts("pow2:8"), Ok(vec![1, 2, 4, 8]));
+        assert_eq!(parse_thread_counts("pow2:10"), Ok(vec![1, 2, 4, 8]));
+        assert_eq!(parse_thread_counts("pow2:3:16"), Ok(vec![4, 8, 16]));
+        assert_eq!(parse_thread_counts("pow2:4:4"), Ok(vec![4]));
+        assert!(parse_thread_counts("pow2:5:7").is_err());
+        assert!(parse_thread_counts("pow2:0").is_err());
+        assert!(parse_thread_counts("pow2:1:2:3").is_err());
+    }
+}
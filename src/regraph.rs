@@ -0,0 +1,95 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::Path;
+
+use crate::grapher::{draw_memory_graphs, graph_name, metric_graph_name, topology_markers, Graph, GraphOptions, Series};
+use crate::metadata::{Metadata, METADATA_FILE};
+use crate::results::{Metric, ResultFormat, ResultSet, RunRecord};
+
+/// Rebuilds the graphs of a previous run from its stored results, without running any benchmarks
+///
+/// Only the given benchmarks and allocators are graphed, or all of them if `None`, and the given metrics are
+/// graphed next to the throughput, along with the memory over time of runs that had it sampled. The graphs are written into `output` along with the results they were made
+/// from and the metadata of the original run.
+pub fn regraph(
+    results_dir: &Path,
+    output: &Path,
+    benchmarks: Option<&[&str]>,
+    allocators: Option<&[&str]>,
+    metrics: &[Metric],
+    options: &GraphOptions
+) -> Result<(), Box<dyn Error>> {
+    let results = ResultSet::load(results_dir)
+        .map_err(|e| format!("Could not load the results in {:?}: {}", results_dir, e))?;
+
+    let runs: Vec<RunRecord> = results.runs.into_iter()
+        .filter(|run| benchmarks.is_none_or(|b| b.contains(&run.benchmark.as_str())))
+        .filter(|run| allocators.is_none_or(|a| a.iter().any(|name| run.is_allocator(name))))
+        .collect();
+    if runs.is_empty() {
+        return Err(format!("No results in {:?} match the selected benchmarks and allocators", results_dir).into());
+    }
+
+    let mut failures: BTreeMap<String, BTreeMap<String, Vec<usize>>> = BTreeMap::new();
+    for run in runs.iter().filter(|run| run.failure.is_some()) {
+        failures.entry(graph_name(&run.benchmark, &run.swept_label()))
+            .or_default()
+            .entry(run.allocator.clone())
+            .or_default()
+            .push(run.threads);
+    }
+
+    // The core and CPU counts are those of the machine the benchmarks ran on
+    let mut options = options.clone();
+    if let Ok(metadata) = Metadata::load(results_dir) {
+        options.thread_markers = topology_markers(&metadata.host);
+    }
+    let options = &options;
+
+    std::fs::create_dir_all(output)?;
+    let metrics = std::iter::once(Metric::Throughput).chain(metrics.iter().copied().filter(|m| *m != Metric::Throughput));
+    for metric in metrics {
+        let mut graphs: BTreeMap<String, BTreeMap<String, Series>> = BTreeMap::new();
+        for run in runs.iter().filter(|run| run.failure.is_none()) {
+            if let Some(samples) = run.samples(metric) {
+                graphs.entry(graph_name(&run.benchmark, &run.swept_label()))
+                    .or_default()
+                    .entry(run.allocator.clone())
+                    .or_default()
+                    .push((run.threads, samples));
+            }
+        }
+        // Graphs where every point failed only show the failures
+        for name in failures.keys() {
+            graphs.entry(name.clone()).or_default();
+        }
+        for (name, mut results) in graphs {
+            for series in results.values_mut() {
+                series.sort_by_key(|(threads, _)| *threads);
+            }
+            let failed = failures.get(&name).cloned().unwrap_or_default();
+            let options = options.for_metric(&name, metric);
+            Graph::new(metric_graph_name(&name, metric), results, &options).with_failures(failed).make_graph(output)?;
+        }
+    }
+
+    let mut graph_runs: BTreeMap<String, Vec<&RunRecord>> = BTreeMap::new();
+    for run in &runs {
+        graph_runs.entry(graph_name(&run.benchmark, &run.swept_label())).or_default().push(run);
+    }
+    for (name, runs) in graph_runs {
+        draw_memory_graphs(&name, runs.into_iter(), results_dir, options, output)?;
+    }
+
+    let metadata = results_dir.join(METADATA_FILE);
+    if metadata.exists() {
+        std::fs::copy(metadata, output.join(METADATA_FILE))?;
+    }
+    let failures = results.failures.into_iter()
+        .filter(|f| benchmarks.is_none_or(|b| f.benchmark.as_ref().is_none_or(|name| b.iter().any(|b| name.starts_with(b)))))
+        .filter(|f| allocators.is_none_or(|a| f.allocator.as_ref().is_none_or(|name| a.iter().any(|a| name.starts_with(a)))))
+        .collect();
+    ResultSet { runs, failures }.save(output, &[ResultFormat::Json])?;
+
+    Ok(())
+}
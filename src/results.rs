@@ -0,0 +1,570 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::rusage::ResourceUsage;
+use crate::stats::{OutlierPolicy, Samples};
+
+/// The name of the structured results files inside of a results directory
+pub const RESULTS_FILE: &str = "results";
+
+pub const RESULT_FORMATS: [&str; 2] = ["json", "csv"];
+
+/// The format that structured results are written in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResultFormat {
+    Json,
+    Csv
+}
+
+impl FromStr for ResultFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(ResultFormat::Json),
+            "csv" => Ok(ResultFormat::Csv),
+            other => Err(format!("{} is not a result format, expected one of {:?}", other, RESULT_FORMATS))
+        }
+    }
+}
+
+impl Display for ResultFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ResultFormat::Json => write!(f, "json"),
+            ResultFormat::Csv => write!(f, "csv")
+        }
+    }
+}
+
+pub const METRICS: [&str; 14] = [
+    "throughput",
+    "max-rss",
+    "minor-faults",
+    "major-faults",
+    "voluntary-switches",
+    "involuntary-switches",
+    "user-time",
+    "sys-time",
+    "cycles",
+    "instructions",
+    "cache-misses",
+    "dtlb-misses",
+    "page-faults",
+    "task-clock"
+];
+
+/// A value measured in every trial, which can be graphed against the number of threads
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Metric {
+    Throughput,
+    MaxRss,
+    MinorFaults,
+    MajorFaults,
+    VoluntarySwitches,
+    InvoluntarySwitches,
+    UserTime,
+    SysTime,
+    /// The perf event counters, which are only measured with `--counters`
+    Cycles,
+    Instructions,
+    CacheMisses,
+    DtlbMisses,
+    PageFaults,
+    TaskClock
+}
+
+impl FromStr for Metric {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "throughput" => Ok(Metric::Throughput),
+            "max-rss" => Ok(Metric::MaxRss),
+            "minor-faults" => Ok(Metric::MinorFaults),
+            "major-faults" => Ok(Metric::MajorFaults),
+            "voluntary-switches" => Ok(Metric::VoluntarySwitches),
+            "involuntary-switches" => Ok(Metric::InvoluntarySwitches),
+            "user-time" => Ok(Metric::UserTime),
+            "sys-time" => Ok(Metric::SysTime),
+            "cycles" => Ok(Metric::Cycles),
+            "instructions" => Ok(Metric::Instructions),
+            "cache-misses" => Ok(Metric::CacheMisses),
+            "dtlb-misses" => Ok(Metric::DtlbMisses),
+            "page-faults" => Ok(Metric::PageFaults),
+            "task-clock" => Ok(Metric::TaskClock),
+            other => Err(format!("{} is not a metric, expected one of {:?}", other, METRICS))
+        }
+    }
+}
+
+impl Display for Metric {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Metric::Throughput => "throughput",
+            Metric::MaxRss => "max-rss",
+            Metric::MinorFaults => "minor-faults",
+            Metric::MajorFaults => "major-faults",
+            Metric::VoluntarySwitches => "voluntary-switches",
+            Metric::InvoluntarySwitches => "involuntary-switches",
+            Metric::UserTime => "user-time",
+            Metric::SysTime => "sys-time",
+            Metric::Cycles => "cycles",
+            Metric::Instructions => "instructions",
+            Metric::CacheMisses => "cache-misses",
+            Metric::DtlbMisses => "dtlb-misses",
+            Metric::PageFaults => "page-faults",
+            Metric::TaskClock => "task-clock"
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl Metric {
+
+    /// What the metric is, with its unit, for the axis of its graphs
+    pub fn label(&self) -> &'static str {
+        match self {
+            Metric::Throughput => "Throughput",
+            Metric::MaxRss => "Peak RSS (MB)",
+            Metric::MinorFaults => "Minor Page Faults",
+            Metric::MajorFaults => "Major Page Faults",
+            Metric::VoluntarySwitches => "Voluntary Context Switches",
+            Metric::InvoluntarySwitches => "Involuntary Context Switches",
+            Metric::UserTime => "User CPU Time (s)",
+            Metric::SysTime => "System CPU Time (s)",
+            Metric::Cycles => "CPU Cycles",
+            Metric::Instructions => "Instructions",
+            Metric::CacheMisses => "Cache Misses",
+            Metric::DtlbMisses => "dTLB Load Misses",
+            Metric::PageFaults => "Page Faults",
+            Metric::TaskClock => "Task Clock (ms)"
+        }
+    }
+
+    /// The value of the metric in a trial, if it was measured
+    pub fn value(&self, trial: &TrialRecord) -> Option<f64> {
+        let usage = trial.usage.as_ref();
+        match self {
+            Metric::Throughput => Some(trial.throughput),
+            Metric::MaxRss => usage.map(|u| u.max_rss as f64 / 1024.0),
+            Metric::MinorFaults => usage.map(|u| u.minor_faults as f64),
+            Metric::MajorFaults => usage.map(|u| u.major_faults as f64),
+            Metric::VoluntarySwitches => usage.map(|u| u.voluntary_switches as f64),
+            Metric::InvoluntarySwitches => usage.map(|u| u.involuntary_switches as f64),
+            Metric::UserTime => usage.map(|u| u.user_time),
+            Metric::SysTime => usage.map(|u| u.sys_time),
+            // The task clock is counted in nanoseconds
+            Metric::TaskClock => trial.counters.get("task-clock").map(|ns| *ns as f64 / 1_000_000.0),
+            // The other counters are stored under the name of their metric
+            _ => trial.counters.get(&self.to_string()).map(|count| *count as f64)
+        }
+    }
+}
+
+/// A single execution of a benchmark binary
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrialRecord {
+    pub index: usize,
+    /// Whether this was a warmup run, which is not counted in the results
+    pub warmup: bool,
+    /// The wall time of the run, in seconds
+    pub wall_time: f64,
+    pub throughput: f64,
+    pub success: bool,
+    /// The exit code of the process, which is missing if it was killed by a signal
+    pub exit_code: Option<i32>,
+    /// Where the standard output of the run was saved
+    pub stdout: PathBuf,
+    /// The resources used by the run, which results from older versions don't have
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<ResourceUsage>,
+    /// Where the memory samples of the run were saved, if it was sampled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory: Option<PathBuf>,
+    /// The perf event counts of the run, by counter name, if they were counted
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub counters: BTreeMap<String, u64>
+}
+
+/// Every trial of one point: a benchmark, allocator, set of parameters and thread count
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunRecord {
+    pub benchmark: String,
+    /// The series the point belongs to, which is the allocator name followed by its APF variables, if any
+    pub allocator: String,
+    /// The APF tuning variables set in the environment of the benchmark
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// The value of every parameter of the benchmark
+    pub params: BTreeMap<String, String>,
+    /// The parameters that were swept over
+    pub swept: Vec<String>,
+    pub threads: usize,
+    /// How outliers are removed from the trials
+    pub outliers: OutlierPolicy,
+    pub trials: Vec<TrialRecord>,
+    /// The CPU affinity policy the point was run with, if it was pinned
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub affinity: Option<String>,
+    /// The CPUs the point was pinned to
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cpus: Vec<usize>,
+    /// Why the point failed, if it did
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub failure: Option<String>
+}
+
+/// A failure to build, link, run or graph, which was skipped with `--keep-going`, or stopped the run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FailureRecord {
+    /// build, link, run, graph or results
+    pub stage: String,
+    /// The benchmark, or the graph, that failed
+    pub benchmark: Option<String>,
+    /// The allocator, or the series, that failed
+    pub allocator: Option<String>,
+    pub threads: Option<usize>,
+    pub message: String
+}
+
+/// All of the results of a run of the platform
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ResultSet {
+    pub runs: Vec<RunRecord>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub failures: Vec<FailureRecord>
+}
+
+impl RunRecord {
+
+    /// The throughput of every successful trial that isn't a warmup
+    pub fn throughputs(&self) -> Vec<f64> {
+        self.trials.iter().filter(|t| !t.warmup && t.success).map(|t| t.throughput).collect()
+    }
+
+    /// The samples of a metric over every successful trial that isn't a warmup, if any of them measured it
+    pub fn samples(&self, metric: Metric) -> Option<Samples> {
+        let values: Vec<f64> = self.trials.iter()
+            .filter(|t| !t.warmup && t.success)
+            .filter_map(|t| metric.value(t))
+            .collect();
+        if values.is_empty() {
+            None
+        } else {
+            Some(Samples::new(&values, self.outliers))
+        }
+    }
+
+    /// Whether the point belongs to the allocator or variant, which is either the whole series name
+    /// or the start of it, such as `apfmalloc` or `apfmalloc[no_met_stack]` for `apfmalloc[no_met_stack][TARGET_APF=2500]`
+    pub fn is_allocator(&self, name: &str) -> bool {
+        self.allocator.strip_prefix(name).is_some_and(|rest| rest.is_empty() || rest.starts_with(['[', '@']))
+    }
+
+    /// The swept parameters, as `name=value,name=value`
+    pub fn swept_label(&self) -> String {
+        let swept: Vec<String> = self.swept.iter().map(|name| format!("{}={}", name, self.params[name])).collect();
+        swept.join(",")
+    }
+}
+
+/// The columns of `results.csv`: those of the point, those of one of its trials, and the rest of the point
+const CSV_POINT_COLUMNS: [&str; 5] = ["benchmark", "allocator", "params", "env", "threads"];
+const CSV_TRIAL_COLUMNS: [&str; 16] = [
+    "trial", "warmup", "wall_time", "throughput", "success", "exit_code", "stdout", "max_rss_kb", "minor_faults",
+    "major_faults", "voluntary_switches", "involuntary_switches", "user_time", "sys_time", "memory", "counters"
+];
+const CSV_POINT_END_COLUMNS: [&str; 3] = ["affinity", "cpus", "failure"];
+
+/// Quotes a CSV field if it contains a separator, quote or newline
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+impl ResultSet {
+
+    /// Loads the JSON results stored in a results directory
+    pub fn load<P: AsRef<Path>>(dir: P) -> Result<Self, std::io::Error> {
+        let path = dir.as_ref().join(format!("{}.json", RESULTS_FILE));
+        let file = File::open(path)?;
+        serde_json::from_reader(file).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+    }
+
+    /// Writes the results into the directory in the given formats, replacing any previous results
+    pub fn save<P: AsRef<Path>>(&self, dir: P, formats: &[ResultFormat]) -> Result<(), std::io::Error> {
+        for format in formats {
+            let path = dir.as_ref().join(format!("{}.{}", RESULTS_FILE, format));
+            let mut writer = BufWriter::new(File::create(path)?);
+            match format {
+                ResultFormat::Json => {
+                    serde_json::to_writer_pretty(&mut writer, self)
+                        .map_err(std::io::Error::other)?;
+                },
+                ResultFormat::Csv => self.write_csv(&mut writer)?
+            }
+            writer.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Writes one row per trial
+    fn write_csv<W: Write>(&self, writer: &mut W) -> Result<(), std::io::Error> {
+        let header: Vec<&str> = CSV_POINT_COLUMNS.iter().chain(&CSV_TRIAL_COLUMNS).chain(&CSV_POINT_END_COLUMNS).copied().collect();
+        writeln!(writer, "{}", header.join(","))?;
+        for run in &self.runs {
+            let params: Vec<String> = run.params.iter().map(|(name, value)| format!("{}={}", name, value)).collect();
+            let env: Vec<String> = run.env.iter().map(|(name, value)| format!("{}={}", name, value)).collect();
+            let cpus: Vec<String> = run.cpus.iter().map(|cpu| cpu.to_string()).collect();
+            let point: [String; CSV_POINT_COLUMNS.len()] = [
+                csv_field(&run.benchmark),
+                csv_field(&run.allocator),
+                csv_field(&params.join(";")),
+                csv_field(&env.join(";")),
+                run.threads.to_string()
+            ];
+            let point_end: [String; CSV_POINT_END_COLUMNS.len()] = [
+                csv_field(run.affinity.as_deref().unwrap_or("")),
+                csv_field(&cpus.join(";")),
+                csv_field(run.failure.as_deref().unwrap_or(""))
+            ];
+            let mut rows: Vec<[String; CSV_TRIAL_COLUMNS.len()]> = vec![];
+            for trial in &run.trials {
+                let counters: Vec<String> = trial.counters.iter().map(|(name, count)| format!("{}={}", name, count)).collect();
+                let usage = trial.usage.map_or_else(Default::default, |u| [
+                    u.max_rss.to_string(),
+                    u.minor_faults.to_string(),
+                    u.major_faults.to_string(),
+                    u.voluntary_switches.to_string(),
+                    u.involuntary_switches.to_string(),
+                    u.user_time.to_string(),
+                    u.sys_time.to_string()
+                ]);
+                let [max_rss, minor_faults, major_faults, voluntary_switches, involuntary_switches, user_time, sys_time] = usage;
+                rows.push([
+                    trial.index.to_string(),
+                    trial.warmup.to_string(),
+                    trial.wall_time.to_string(),
+                    trial.throughput.to_string(),
+                    trial.success.to_string(),
+                    trial.exit_code.map_or(String::new(), |c| c.to_string()),
+                    csv_field(&trial.stdout.to_string_lossy()),
+                    max_rss,
+                    minor_faults,
+                    major_faults,
+                    voluntary_switches,
+                    involuntary_switches,
+                    user_time,
+                    sys_time,
+                    trial.memory.as_ref().map_or(String::new(), |path| csv_field(&path.to_string_lossy())),
+                    csv_field(&counters.join(";"))
+                ]);
+            }
+            // A point that failed before any of its trials ran still gets a row, with the trial columns empty
+            if rows.is_empty() && run.failure.is_some() {
+                rows.push(Default::default());
+            }
+            for trial in rows {
+                let fields: Vec<&str> = point.iter().chain(&trial).chain(&point_end).map(|field| field.as_str()).collect();
+                writeln!(writer, "{}", fields.join(","))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trial(index: usize, throughput: f64) -> TrialRecord {
+        TrialRecord {
+            index,
+            warmup: index == 0,
+            wall_time: 1.5,
+            throughput,
+            success: true,
+            exit_code: Some(0),
+            stdout: PathBuf::from(format!("stdout/{}.txt", index)),
+            usage: None,
+            memory: None,
+            counters: BTreeMap::new()
+        }
+    }
+
+    fn results() -> ResultSet {
+        let mut counted = trial(2, 30.0);
+        counted.usage = Some(ResourceUsage { max_rss: 2048, ..Default::default() });
+        counted.counters.insert(String::from("cycles"), 1000);
+        counted.counters.insert(String::from("task-clock"), 2_000_000);
+        let params: BTreeMap<String, String> = [("size", "64"), ("mode", "a,b")].iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        ResultSet {
+            runs: vec![
+                RunRecord {
+                    benchmark: String::from("t-test1"),
+                    allocator: String::from("apfmalloc[no_met_stack]"),
+                    env: BTreeMap::new(),
+                    params: params.clone(),
+                    swept: vec![String::from("size")],
+                    threads: 2,
+                    outliers: OutlierPolicy::Iqr,
+                    trials: vec![trial(0, 5.0), trial(1, 10.0), counted],
+                    affinity: Some(String::from("compact")),
+                    cpus: vec![0, 1],
+                    failure: None
+                },
+                RunRecord {
+                    benchmark: String::from("t-test1"),
+                    allocator: String::from("libc"),
+                    env: BTreeMap::new(),
+                    params,
+                    swept: vec![String::from("size")],
+                    threads: 4,
+                    outliers: OutlierPolicy::None,
+                    trials: vec![],
+                    affinity: None,
+                    cpus: vec![],
+                    failure: Some(String::from("Failed to link \"t-test1\""))
+                }
+            ],
+            failures: vec![FailureRecord {
+                stage: String::from("link"),
+                benchmark: Some(String::from("t-test1")),
+                allocator: Some(String::from("libc")),
+                threads: None,
+                message: String::from("exit status: 1")
+            }]
+        }
+    }
+
+    /// Splits a CSV line into its fields, unquoting them
+    fn csv_fields(line: &str) -> Vec<String> {
+        let mut fields = vec![String::new()];
+        let mut quoted = false;
+        let mut chars = line.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '"' if quoted && chars.peek() == Some(&'"') => {
+                    chars.next();
+                    fields.last_mut().unwrap().push('"');
+                },
+                '"' => quoted = !quoted,
+                ',' if !quoted => fields.push(String::new()),
+                c => fields.last_mut().unwrap().push(c)
+            }
+        }
+        fields
+    }
+
+    #[test]
+    fn csv_fields_are_quoted() {
+        assert_eq!(csv_field("plain"), "plain");
+        assert_eq!(csv_field(""), "");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+    }
+
+    #[test]
+    fn csv_rows_match_the_header() {
+        let mut csv = vec![];
+        results().write_csv(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        let header = csv_fields(lines[0]);
+        assert_eq!(header.len(), CSV_POINT_COLUMNS.len() + CSV_TRIAL_COLUMNS.len() + CSV_POINT_END_COLUMNS.len());
+        // Three trials of the first point, and one row for the point that failed before running
+        assert_eq!(lines.len(), 5);
+
+        let rows: Vec<BTreeMap<&str, String>> = lines[1..].iter()
+            .map(|line| {
+                let fields = csv_fields(line);
+                assert_eq!(fields.len(), header.len(), "{}", line);
+                header.iter().map(String::as_str).zip(fields).collect()
+            })
+            .collect();
+        assert_eq!(rows[0]["params"], "mode=a,b;size=64");
+        assert_eq!(rows[0]["warmup"], "true");
+        assert_eq!(rows[0]["max_rss_kb"], "");
+        assert_eq!(rows[2]["max_rss_kb"], "2048");
+        assert_eq!(rows[2]["counters"], "cycles=1000;task-clock=2000000");
+        assert_eq!(rows[2]["cpus"], "0;1");
+        assert_eq!(rows[3]["allocator"], "libc");
+        assert_eq!(rows[3]["trial"], "");
+        assert_eq!(rows[3]["failure"], "Failed to link \"t-test1\"");
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let dir = std::env::temp_dir().join(format!("lrmalloc-rs-testing-results-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let saved = results();
+        saved.save(&dir, &[ResultFormat::Json, ResultFormat::Csv]).unwrap();
+        assert!(dir.join("results.csv").is_file());
+        let loaded = ResultSet::load(&dir).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(loaded.runs.len(), 2);
+        assert_eq!(loaded.failures.len(), 1);
+        assert_eq!(loaded.failures[0].stage, "link");
+        let (run, failed) = (&loaded.runs[0], &loaded.runs[1]);
+        assert_eq!(run.allocator, saved.runs[0].allocator);
+        assert_eq!(run.params, saved.runs[0].params);
+        assert_eq!(run.outliers, OutlierPolicy::Iqr);
+        assert_eq!(run.cpus, [0, 1]);
+        assert_eq!(run.throughputs(), [10.0, 30.0]);
+        assert_eq!(run.trials[2].usage.map(|u| u.max_rss), Some(2048));
+        assert_eq!(Metric::TaskClock.value(&run.trials[2]), Some(2.0));
+        assert_eq!(Metric::Cycles.value(&run.trials[1]), None);
+        assert!(run.trials[1].memory.is_none());
+        assert_eq!(failed.failure, saved.runs[1].failure);
+        assert!(failed.samples(Metric::Throughput).is_none());
+    }
+
+    #[test]
+    fn older_results_load() {
+        // Results from before resource usage, counters, affinity and failures were recorded
+        let json = r#"{"runs": [{"benchmark": "t-test1", "allocator": "libc", "params": {}, "swept": [], "threads": 1,
+            "outliers": "none", "trials": [{"index": 0, "warmup": false, "wall_time": 1.0, "throughput": 2.0,
+            "success": true, "exit_code": 0, "stdout": "stdout/0.txt"}]}]}"#;
+        let results: ResultSet = serde_json::from_str(json).unwrap();
+        assert!(results.failures.is_empty());
+        let run = &results.runs[0];
+        assert!(run.env.is_empty() && run.cpus.is_empty() && run.affinity.is_none() && run.failure.is_none());
+        assert_eq!(Metric::MaxRss.value(&run.trials[0]), None);
+        assert_eq!(run.samples(Metric::Throughput).map(|s| s.value()), Some(2.0));
+    }
+
+    #[test]
+    fn allocator_prefixes() {
+        let run = &results().runs[0];
+        assert!(run.is_allocator("apfmalloc"));
+        assert!(run.is_allocator("apfmalloc[no_met_stack]"));
+        assert!(!run.is_allocator("apf"));
+        assert!(!run.is_allocator("libc"));
+        assert_eq!(run.swept_label(), "size=64");
+    }
+
+    #[test]
+    fn metric_and_format_names() {
+        for name in METRICS {
+            assert_eq!(name.parse::<Metric>().unwrap().to_string(), name);
+        }
+        for name in RESULT_FORMATS {
+            assert_eq!(name.parse::<ResultFormat>().unwrap().to_string(), name);
+        }
+        assert!("xml".parse::<ResultFormat>().is_err());
+    }
+}
@@ -0,0 +1,308 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::iter::FromIterator;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::Ordering;
+use std::time::Instant;
+
+use crate::affinity::pin;
+use crate::allocators::AllocatorSpec;
+use crate::benchmark::{binary_path, BenchmarkError, TrialSettings};
+use crate::error::{PlatformError, RunFailure};
+use crate::grapher::file_name;
+use crate::params::Combination;
+use crate::results::{RunRecord, TrialRecord};
+use crate::rusage::{output_with_usage, Measurements};
+use crate::sampler::{save_samples, MEMORY_DIR};
+use crate::stats::Samples;
+use crate::{BENCHMARK_RESULTS, DYNAMIC_MODE};
+
+/// The folder of a run where the standard output of every trial is saved
+pub const STDOUT_DIR: &str = "stdout";
+
+/// One series of a benchmark: the binary linked with an allocator, run with a set of APF tuning variables
+pub struct SeriesRun {
+    /// The name of the series in the results and graphs
+    pub series: String,
+    pub spec: AllocatorSpec,
+    /// The variables set in the environment of the benchmark
+    pub env: BTreeMap<String, String>,
+    binary_name: String,
+    binary_path: PathBuf,
+    /// The text log of the series in the benchmark results folder
+    writer: BufWriter<File>,
+    /// How many runs have been made of each point, by combination and thread count
+    runs: HashMap<(String, usize), usize>
+}
+
+impl SeriesRun {
+
+    /// Finds the binary of the benchmark for the allocator and creates the text log of the series
+    pub fn new(
+        benchmark: &str,
+        allocator: &str,
+        spec: &AllocatorSpec,
+        env: &Combination,
+        settings: &TrialSettings
+    ) -> Result<Self, PlatformError> {
+        let binary_path = binary_path(benchmark, spec.lib_name());
+        let binary_name = binary_path.file_name().unwrap().to_string_lossy().to_string();
+
+        if !binary_path.exists() {
+            return Err(PlatformError::Link {
+                benchmark: benchmark.to_string(),
+                allocator: Some(allocator.to_string()),
+                error: BenchmarkError::IO(std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    format!("{:?} does not exist", binary_path)
+                ))
+            });
+        }
+
+        let suffix = if env.swept().is_empty() {
+            String::new()
+        } else {
+            format!("[{}]", env)
+        };
+        let binary_name = format!("{}{}", binary_name, suffix);
+
+        let output_file_name = format!("{}.txt", file_name(&binary_name));
+        let output_file_path = PathBuf::from_iter(&[BENCHMARK_RESULTS, output_file_name.as_str()]);
+        let output_file =
+            OpenOptions::new()
+                .create(true)
+                .write(true)
+                .truncate(true)
+                .open(output_file_path)?;
+
+        let mut writer = BufWriter::new(output_file);
+        writeln!(writer, "{}", settings)?;
+
+        Ok(Self {
+            series: format!("{}{}", allocator, suffix),
+            spec: spec.clone(),
+            env: env.values().clone(),
+            binary_name,
+            binary_path,
+            writer,
+            runs: HashMap::new()
+        })
+    }
+
+    /// Runs the warmup runs and trials of one point
+    ///
+    /// Returns the record of every run, and the kept samples of the trials, or why the point failed.
+    /// Running a point again continues the numbering of its runs, so that earlier outputs are kept. The
+    /// outputs and memory samples of the runs are saved in the folder of the run, `run_dir`.
+    pub fn run_point(
+        &mut self,
+        benchmark: &str,
+        combination: &Combination,
+        thread_count: usize,
+        args: Vec<String>,
+        settings: &TrialSettings,
+        run_dir: &Path
+    ) -> (RunRecord, Result<Samples, PlatformError>) {
+        let run_name = if combination.swept().is_empty() {
+            format!("{} with {} threads", self.binary_name, thread_count)
+        } else {
+            format!("{} with {} threads ({})", self.binary_name, thread_count, combination)
+        };
+        println!("Running {}", run_name);
+
+        let mut record = RunRecord {
+            benchmark: benchmark.to_string(),
+            allocator: self.series.clone(),
+            env: self.env.clone(),
+            params: combination.values().clone(),
+            swept: combination.swept().into_iter().map(|(param, _)| param.to_string()).collect(),
+            threads: thread_count,
+            outliers: settings.outliers,
+            trials: vec![],
+            affinity: None,
+            cpus: vec![],
+            failure: None
+        };
+        let cpus = settings.affinity.cpus(thread_count);
+        if let Some(cpus) = &cpus {
+            record.affinity = Some(settings.affinity.to_string());
+            record.cpus = cpus.clone();
+        }
+        let first = self.runs.entry((combination.to_string(), thread_count)).or_insert(0);
+        let start = *first;
+        *first += settings.warmup + settings.count;
+
+        let (binary_path, binary_name, env, spec) = (&self.binary_path, &self.binary_name, &self.env, &self.spec);
+        let writer = &mut self.writer;
+        let mut run_trials = || -> Result<Samples, RunFailure> {
+            writeln!(writer, "-------------- [START] {} --------------", run_name)?;
+            let mut samples = vec![];
+            for (run, i) in (0..settings.warmup + settings.count).zip(start..) {
+                let is_warmup = run < settings.warmup;
+                if is_warmup {
+                    writeln!(
+                        writer,
+                        "---- ))Start Warmup {} ----",
+                        run
+                    )?;
+                } else {
+                    writeln!(
+                        writer,
+                        "---- ))Start Iteration {} ----",
+                        run - settings.warmup
+                    )?;
+                }
+
+
+                let mut command = Command::new(binary_path.to_str().unwrap());
+                command
+                    .args(args.clone())
+                    .envs(env.iter());
+                if let Some(cpus) = &cpus {
+                    pin(&mut command, cpus);
+                }
+                if DYNAMIC_MODE.load(Ordering::Acquire) {
+                    if let Some(path) = spec.lib_file(true) {
+                        let path = match path.canonicalize() {
+                            Ok(path) => path,
+                            Err(e) => {
+                                let e = std::io::Error::new(e.kind(), format!("the dynamic library {:?} is missing", path));
+                                return Err(RunFailure::Spawn(e));
+                            }
+                        };
+
+
+                        #[cfg(target_os = "linux")]
+                            command.env("LD_PRELOAD", path);
+                        #[cfg(target_os = "macos")]
+                            command.env("DYLD_INSERT_LIBRARIES", path);
+                    }
+                }
+                let start = Instant::now();
+                let (output, measured) = match output_with_usage(&mut command, settings.memory_interval, settings.counters) {
+                    Ok(output) => output,
+                    Err(e) => {
+                        writeln!(writer, "PROGRAM COULD NOT BE STARTED\n-------------- [END] --------------")?;
+                        return Err(RunFailure::Spawn(e));
+                    }
+                };
+                let duration = start.elapsed();
+
+                let trial_name = {
+                    let combination_name = if combination.swept().is_empty() {
+                        String::new()
+                    } else {
+                        format!("[{}]", combination)
+                    };
+                    file_name(&format!("{}{}-{}-{}", binary_name, combination_name, thread_count, i))
+                };
+                let stdout_path = run_dir.join(STDOUT_DIR).join(format!("{}.txt", trial_name));
+                std::fs::write(&stdout_path, &output.stdout)?;
+                let Measurements { usage, memory, counters } = measured;
+                let memory_path = if memory.is_empty() {
+                    None
+                } else {
+                    let path = run_dir.join(MEMORY_DIR).join(format!("{}.csv", trial_name));
+                    save_samples(&path, &memory)?;
+                    Some(path)
+                };
+                let throughput = 1.0 / duration.as_secs_f64();
+                record.trials.push(TrialRecord {
+                    index: i,
+                    warmup: is_warmup,
+                    wall_time: duration.as_secs_f64(),
+                    throughput,
+                    success: output.status.success(),
+                    exit_code: output.status.code(),
+                    stdout: stdout_path,
+                    usage: Some(usage),
+                    memory: memory_path,
+                    counters
+                });
+
+                if !output.status.success() {
+                    writeln!(
+                        writer,
+                        "PROGRAM CRASHED\n-------------- [END] --------------"
+                    )?;
+                    return Err(RunFailure::Crashed(output.status));
+                }
+                let output = String::from_utf8_lossy(&output.stdout);
+                println!("{}", output);
+                writeln!(
+                    writer,
+                    "{}",
+                    output
+                )?;
+
+                writeln!(
+                    writer,
+                    "Throughput: {}",
+                    throughput
+                )?;
+                writeln!(
+                    writer,
+                    "Peak RSS: {} KB, page faults: {} minor, {} major, context switches: {} voluntary, {} involuntary, CPU time: {}s user, {}s system",
+                    usage.max_rss,
+                    usage.minor_faults,
+                    usage.major_faults,
+                    usage.voluntary_switches,
+                    usage.involuntary_switches,
+                    usage.user_time,
+                    usage.sys_time
+                )?;
+                let counters = &record.trials.last().unwrap().counters;
+                if !counters.is_empty() {
+                    let counts: Vec<String> = counters.iter().map(|(name, count)| format!("{} {}", count, name)).collect();
+                    writeln!(writer, "Counters: {}", counts.join(", "))?;
+                }
+                if !is_warmup {
+                    samples.push(throughput);
+                }
+            }
+            let kept = Samples::new(&samples, settings.outliers);
+            if kept.samples().len() < samples.len() {
+                writeln!(
+                    writer,
+                    "Rejected {} of {} trials as outliers",
+                    samples.len() - kept.samples().len(),
+                    samples.len()
+                )?;
+            }
+            writeln!(
+                writer,
+                "#### {} Throughput: {} ####",
+                settings.outliers.aggregate_name(),
+                kept.value()
+            )?;
+            writeln!(
+                writer,
+                "#### {} ####",
+                kept
+            )?;
+            writeln!(
+                writer,
+                "-------------- [END] --------------"
+            )?;
+            Ok(kept)
+        };
+        let kept = run_trials();
+        // The log is flushed after every point, so that it is complete up to a failure
+        let flushed = self.writer.flush();
+        match kept.and_then(|kept| flushed.map(|_| kept).map_err(RunFailure::from)) {
+            Ok(kept) => (record, Ok(kept)),
+            Err(failure) => {
+                let error = PlatformError::Run {
+                    benchmark: benchmark.to_string(),
+                    series: self.series.clone(),
+                    threads: thread_count,
+                    failure
+                };
+                record.failure = Some(error.to_string());
+                (record, Err(error))
+            }
+        }
+    }
+}
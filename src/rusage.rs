@@ -0,0 +1,111 @@
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::os::unix::process::ExitStatusExt;
+use std::process::{Command, ExitStatus, Output, Stdio};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::counters::Counters;
+use crate::sampler::{MemorySample, MemorySampler};
+
+/// The resources used by a run of a benchmark, as reported by the kernel when it is reaped
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct ResourceUsage {
+    /// The peak resident set size, in kilobytes
+    pub max_rss: u64,
+    /// Page faults that were served without reading from disk
+    pub minor_faults: u64,
+    pub major_faults: u64,
+    pub voluntary_switches: u64,
+    pub involuntary_switches: u64,
+    /// The CPU time spent in user mode, in seconds
+    pub user_time: f64,
+    /// The CPU time spent in the kernel, in seconds
+    pub sys_time: f64
+}
+
+fn seconds(time: libc::timeval) -> f64 {
+    time.tv_sec as f64 + time.tv_usec as f64 / 1_000_000.0
+}
+
+impl From<&libc::rusage> for ResourceUsage {
+    fn from(usage: &libc::rusage) -> Self {
+        // Linux reports the peak RSS in kilobytes, and macOS in bytes
+        #[cfg(target_os = "macos")]
+            let max_rss = usage.ru_maxrss as u64 / 1024;
+        #[cfg(not(target_os = "macos"))]
+            let max_rss = usage.ru_maxrss as u64;
+        Self {
+            max_rss,
+            minor_faults: usage.ru_minflt as u64,
+            major_faults: usage.ru_majflt as u64,
+            voluntary_switches: usage.ru_nvcsw as u64,
+            involuntary_switches: usage.ru_nivcsw as u64,
+            user_time: seconds(usage.ru_utime),
+            sys_time: seconds(usage.ru_stime)
+        }
+    }
+}
+
+/// What was measured of a run of a benchmark, besides its output
+pub struct Measurements {
+    pub usage: ResourceUsage,
+    /// The memory of the run over time, if it was sampled
+    pub memory: Vec<MemorySample>,
+    /// The perf event counts of the run, by counter name, if they were counted
+    pub counters: BTreeMap<String, u64>
+}
+
+/// Waits for a child to exit and reaps it, returning its exit status and the resources it used
+fn wait4(pid: u32) -> std::io::Result<(ExitStatus, ResourceUsage)> {
+    let mut status = 0;
+    // SAFETY: rusage is plain data, for which all zeroes is a valid value
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    loop {
+        // SAFETY: both pointers are to live locals
+        let reaped = unsafe { libc::wait4(pid as libc::pid_t, &mut status, 0, &mut usage) };
+        if reaped != -1 {
+            break;
+        }
+        let e = std::io::Error::last_os_error();
+        if e.kind() != std::io::ErrorKind::Interrupted {
+            return Err(e);
+        }
+    }
+    Ok((ExitStatus::from_raw(status), ResourceUsage::from(&usage)))
+}
+
+/// Runs a command to completion like `Command::output`, and also returns the resources it used, its memory
+/// sampled at the given interval, if any, and its perf event counts if `count` is set
+pub fn output_with_usage(
+    command: &mut Command,
+    memory_interval: Option<Duration>,
+    count: bool
+) -> std::io::Result<(Output, Measurements)> {
+    // The counters are inherited by the child, so they have to be opened on the thread that starts it
+    let counters = if count { Some(Counters::open()) } else { None };
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let sampler = memory_interval.map(|interval| MemorySampler::start(child.id(), interval, Instant::now()));
+
+    // Standard error is read on another thread, so that neither pipe can fill up and block the child
+    let mut stderr_pipe = child.stderr.take().unwrap();
+    let stderr_reader = std::thread::spawn(move || {
+        let mut stderr = vec![];
+        stderr_pipe.read_to_end(&mut stderr).map(|_| stderr)
+    });
+    let mut stdout = vec![];
+    let read = child.stdout.take().unwrap().read_to_end(&mut stdout);
+    let stderr = stderr_reader.join().unwrap();
+    let memory = sampler.map_or(vec![], MemorySampler::finish);
+
+    // The child is reaped here instead of by `Child::wait`, which doesn't return its resource usage
+    let (status, usage) = wait4(child.id())?;
+    read?;
+    let counters = counters.map_or(BTreeMap::new(), Counters::read);
+    Ok((Output { status, stdout, stderr: stderr? }, Measurements { usage, memory, counters }))
+}
@@ -0,0 +1,113 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// The folder of a run where the memory samples of every trial are saved
+pub const MEMORY_DIR: &str = "memory";
+
+/// The memory of a benchmark at one moment of its run, in kilobytes
+#[derive(Debug, Clone, Copy)]
+pub struct MemorySample {
+    /// The time since the benchmark was started, in seconds
+    pub time: f64,
+    pub rss: u64,
+    /// Anonymous memory, which is where allocators put the memory they get from mmap and brk
+    pub anon: u64,
+    pub swap: u64
+}
+
+/// Reads the `Name:   value kB` lines of a file in /proc
+fn read_kb_fields(path: &str) -> Option<BTreeMap<String, u64>> {
+    let contents = std::fs::read_to_string(path).ok()?;
+    let fields = contents.lines()
+        .filter_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            let value = value.trim().strip_suffix("kB")?.trim().parse().ok()?;
+            Some((name.to_string(), value))
+        })
+        .collect();
+    Some(fields)
+}
+
+/// Samples the memory of a process, or returns `None` if it has exited
+fn sample(pid: u32, time: f64) -> Option<MemorySample> {
+    let status = read_kb_fields(&format!("/proc/{}/status", pid))?;
+    // Processes that have exited but have not been reaped yet have no VmRSS
+    let rss = *status.get("VmRSS")?;
+    // smaps_rollup counts exactly, while the counters in status are approximate, but it needs Linux 4.14
+    let (anon, swap) = match read_kb_fields(&format!("/proc/{}/smaps_rollup", pid)) {
+        Some(rollup) if rollup.contains_key("Anonymous") => (rollup["Anonymous"], rollup.get("Swap").copied().unwrap_or(0)),
+        _ => (status.get("RssAnon").copied().unwrap_or(0), status.get("VmSwap").copied().unwrap_or(0))
+    };
+    Some(MemorySample { time, rss, anon, swap })
+}
+
+/// Polls the memory of a running process from another thread
+pub struct MemorySampler {
+    stop: Arc<AtomicBool>,
+    thread: JoinHandle<Vec<MemorySample>>
+}
+
+impl MemorySampler {
+
+    /// Starts sampling a process at the given interval, with the times of the samples measured from `start`
+    pub fn start(pid: u32, interval: Duration, start: Instant) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        let thread = std::thread::spawn(move || {
+            let mut samples = vec![];
+            while !stopped.load(Ordering::Acquire) {
+                if let Some(sample) = sample(pid, start.elapsed().as_secs_f64()) {
+                    samples.push(sample);
+                }
+                std::thread::park_timeout(interval);
+            }
+            samples
+        });
+        Self { stop, thread }
+    }
+
+    /// Stops sampling and returns the samples. This has to happen before the process is reaped, since its
+    /// pid can be reused afterwards.
+    pub fn finish(self) -> Vec<MemorySample> {
+        self.stop.store(true, Ordering::Release);
+        self.thread.thread().unpark();
+        self.thread.join().unwrap()
+    }
+}
+
+/// Writes memory samples as CSV, with one row per sample
+pub fn save_samples(path: &Path, samples: &[MemorySample]) -> std::io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    writeln!(writer, "time,rss_kb,anon_kb,swap_kb")?;
+    for sample in samples {
+        writeln!(writer, "{},{},{},{}", sample.time, sample.rss, sample.anon, sample.swap)?;
+    }
+    writer.flush()
+}
+
+/// Reads memory samples written by `save_samples`
+pub fn load_samples(path: &Path) -> std::io::Result<Vec<MemorySample>> {
+    let invalid = |line: &str| std::io::Error::new(std::io::ErrorKind::InvalidData, format!("invalid memory sample in {:?}: {}", path, line));
+    let mut samples = vec![];
+    for line in BufReader::new(File::open(path)?).lines().skip(1) {
+        let line = line?;
+        let fields: Vec<&str> = line.split(',').collect();
+        if fields.len() != 4 {
+            return Err(invalid(&line));
+        }
+        let kb = |i: usize| fields[i].parse::<u64>().map_err(|_| invalid(&line));
+        samples.push(MemorySample {
+            time: fields[0].parse().map_err(|_| invalid(&line))?,
+            rss: kb(1)?,
+            anon: kb(2)?,
+            swap: kb(3)?
+        });
+    }
+    Ok(samples)
+}
@@ -0,0 +1,304 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+/// How trials that are far from the rest are handled when combining trials into a single result
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutlierPolicy {
+    /// Every trial is kept, and the result is their mean
+    #[default]
+    None,
+    /// Trials outside of 1.5 times the interquartile range are dropped, and the result is the mean of the rest
+    Iqr,
+    /// Every trial is kept, and the result is their median
+    Median
+}
+
+pub const OUTLIER_POLICIES: [&str; 3] = ["none", "iqr", "median"];
+
+impl FromStr for OutlierPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(OutlierPolicy::None),
+            "iqr" => Ok(OutlierPolicy::Iqr),
+            "median" => Ok(OutlierPolicy::Median),
+            other => Err(format!("{} is not an outlier policy, expected one of {:?}", other, OUTLIER_POLICIES))
+        }
+    }
+}
+
+impl Display for OutlierPolicy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            OutlierPolicy::None => "none",
+            OutlierPolicy::Iqr => "iqr",
+            OutlierPolicy::Median => "median"
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Sorts a copy of the samples, ignoring NaNs
+fn sorted(samples: &[f64]) -> Vec<f64> {
+    let mut sorted: Vec<f64> = samples.iter().copied().filter(|s| !s.is_nan()).collect();
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    sorted
+}
+
+/// The value at the given fraction of the sorted samples, interpolating between neighbours
+fn quantile(sorted: &[f64], fraction: f64) -> f64 {
+    if sorted.is_empty() {
+        return f64::NAN;
+    }
+    let position = fraction * (sorted.len() - 1) as f64;
+    let lower = position.floor() as usize;
+    let upper = position.ceil() as usize;
+    sorted[lower] + (sorted[upper] - sorted[lower]) * (position - lower as f64)
+}
+
+pub fn mean(samples: &[f64]) -> f64 {
+    samples.iter().sum::<f64>() / samples.len() as f64
+}
+
+pub fn median(samples: &[f64]) -> f64 {
+    quantile(&sorted(samples), 0.5)
+}
+
+impl OutlierPolicy {
+
+    /// The samples that are kept under this policy, in their original order
+    pub fn filter(&self, samples: &[f64]) -> Vec<f64> {
+        match self {
+            OutlierPolicy::Iqr if samples.len() >= 4 => {
+                let sorted = sorted(samples);
+                let q1 = quantile(&sorted, 0.25);
+                let q3 = quantile(&sorted, 0.75);
+                let fence = 1.5 * (q3 - q1);
+                samples.iter().copied().filter(|s| *s >= q1 - fence && *s <= q3 + fence).collect()
+            },
+            _ => samples.to_vec()
+        }
+    }
+
+    /// Combines the kept samples into a single result
+    pub fn aggregate(&self, kept: &[f64]) -> f64 {
+        match self {
+            OutlierPolicy::Median => median(kept),
+            _ => mean(kept)
+        }
+    }
+
+    /// The name of the value produced by `aggregate`
+    pub fn aggregate_name(&self) -> &'static str {
+        match self {
+            OutlierPolicy::Median => "Median",
+            _ => "Average"
+        }
+    }
+}
+
+/// Two-sided 95% critical values of Student's t distribution, indexed by the degrees of freedom minus 1
+const T_95: [f64; 30] = [
+    12.706, 4.303, 3.182, 2.776, 2.571, 2.447, 2.365, 2.306, 2.262, 2.228,
+    2.201, 2.179, 2.160, 2.145, 2.131, 2.120, 2.110, 2.101, 2.093, 2.086,
+    2.080, 2.074, 2.069, 2.064, 2.060, 2.056, 2.052, 2.048, 2.045, 2.042
+];
+
+/// The two-sided 95% critical value of Student's t distribution, using the normal distribution past 30 degrees of freedom
+pub fn t_critical_95(degrees_of_freedom: usize) -> f64 {
+    match degrees_of_freedom {
+        0 => f64::NAN,
+        df if df <= T_95.len() => T_95[df - 1],
+        _ => 1.96
+    }
+}
+
+/// The trials kept for a single point, after outliers were removed
+#[derive(Debug, Clone)]
+pub struct Samples {
+    samples: Vec<f64>,
+    policy: OutlierPolicy
+}
+
+impl Samples {
+
+    /// Removes outliers from the trials according to the policy
+    pub fn new(trials: &[f64], policy: OutlierPolicy) -> Self {
+        Self {
+            samples: policy.filter(trials),
+            policy
+        }
+    }
+
+    pub fn samples(&self) -> &[f64] {
+        &self.samples
+    }
+
+    /// The result of the point, as decided by the outlier policy
+    pub fn value(&self) -> f64 {
+        self.policy.aggregate(&self.samples)
+    }
+
+    pub fn mean(&self) -> f64 {
+        mean(&self.samples)
+    }
+
+    pub fn min(&self) -> f64 {
+        self.samples.iter().copied().fold(f64::NAN, f64::min)
+    }
+
+    pub fn max(&self) -> f64 {
+        self.samples.iter().copied().fold(f64::NAN, f64::max)
+    }
+
+    /// The sample standard deviation, which is 0 with fewer than 2 samples
+    pub fn stddev(&self) -> f64 {
+        let n = self.samples.len();
+        if n < 2 {
+            return 0.0;
+        }
+        let mean = self.mean();
+        let variance = self.samples.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / (n - 1) as f64;
+        variance.sqrt()
+    }
+
+    /// The 95% confidence interval of the mean, which is just the mean with fewer than 2 samples
+    pub fn confidence_interval(&self) -> (f64, f64) {
+        let n = self.samples.len();
+        let mean = self.mean();
+        if n < 2 {
+            return (mean, mean);
+        }
+        let half_width = t_critical_95(n - 1) * self.stddev() / (n as f64).sqrt();
+        (mean - half_width, mean + half_width)
+    }
+}
+
+impl Display for Samples {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let (low, high) = self.confidence_interval();
+        write!(
+            f,
+            "Min: {}, Max: {}, Stddev: {}, 95% CI: [{}, {}]",
+            self.min(),
+            self.max(),
+            self.stddev(),
+            low,
+            high
+        )
+    }
+}
+
+/// Whether the means of two sets of samples differ significantly, using Welch's t-test at the 95% level
+///
+/// Returns `None` when either set has fewer than 2 samples, as the test needs their variance.
+pub fn significantly_different(a: &Samples, b: &Samples) -> Option<bool> {
+    let (na, nb) = (a.samples.len() as f64, b.samples.len() as f64);
+    if na < 2.0 || nb < 2.0 {
+        return None;
+    }
+    let (va, vb) = (a.stddev().powi(2) / na, b.stddev().powi(2) / nb);
+    let difference = (a.mean() - b.mean()).abs();
+    if va + vb == 0.0 {
+        return Some(difference > 0.0);
+    }
+    let t = difference / (va + vb).sqrt();
+    let degrees_of_freedom = (va + vb).powi(2) / (va.powi(2) / (na - 1.0) + vb.powi(2) / (nb - 1.0));
+    Some(t > t_critical_95((degrees_of_freedom.floor() as usize).max(1)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn iqr_drops_outliers_in_order() {
+        let trials = [10.0, 11.0, 100.0, 9.0, 10.0];
+        assert_eq!(OutlierPolicy::Iqr.filter(&trials), [10.0, 11.0, 9.0, 10.0]);
+        assert_eq!(OutlierPolicy::None.filter(&trials), trials);
+        assert_eq!(OutlierPolicy::Median.filter(&trials), trials);
+    }
+
+    #[test]
+    fn iqr_keeps_small_and_constant_samples() {
+        assert_eq!(OutlierPolicy::Iqr.filter(&[1.0, 2.0, 100.0]), [1.0, 2.0, 100.0]);
+        assert_eq!(OutlierPolicy::Iqr.filter(&[5.0; 6]), [5.0; 6]);
+    }
+
+    #[test]
+    fn aggregates() {
+        let trials = [4.0, 1.0, 3.0, 100.0];
+        assert_eq!(Samples::new(&trials, OutlierPolicy::None).value(), 27.0);
+        assert_eq!(Samples::new(&trials, OutlierPolicy::Median).value(), 3.5);
+        assert_eq!(Samples::new(&[2.0, 1.0, 3.0], OutlierPolicy::Median).value(), 2.0);
+        assert!(median(&[]).is_nan());
+    }
+
+    #[test]
+    fn stddev_and_confidence_interval() {
+        let samples = Samples::new(&[2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0], OutlierPolicy::None);
+        assert_eq!(samples.mean(), 5.0);
+        assert!((samples.stddev() - (32.0f64 / 7.0).sqrt()).abs() < 1e-12);
+        let (low, high) = samples.confidence_interval();
+        let half_width = 2.365 * samples.stddev() / 8f64.sqrt();
+        assert!((low - (5.0 - half_width)).abs() < 1e-12);
+        assert!((high - (5.0 + half_width)).abs() < 1e-12);
+        assert_eq!((samples.min(), samples.max()), (2.0, 9.0));
+    }
+
+    #[test]
+    fn single_sample_has_no_spread() {
+        let samples = Samples::new(&[3.0], OutlierPolicy::None);
+        assert_eq!(samples.stddev(), 0.0);
+        assert_eq!(samples.confidence_interval(), (3.0, 3.0));
+    }
+
+    #[test]
+    fn t_critical_values() {
+        assert!(t_critical_95(0).is_nan());
+        assert_eq!(t_critical_95(1), 12.706);
+        assert_eq!(t_critical_95(30), 2.042);
+        assert_eq!(t_critical_95(31), 1.96);
+    }
+
+    #[test]
+    fn welch_t_test() {
+        let a = Samples::new(&[10.0, 11.0, 9.0, 10.5, 9.5], OutlierPolicy::None);
+        let b = Samples::new(&[20.0, 21.0, 19.0, 20.5, 19.5], OutlierPolicy::None);
+        let c = Samples::new(&[10.2, 9.8, 10.9, 9.1, 10.0], OutlierPolicy::None);
+        assert_eq!(significantly_different(&a, &b), Some(true));
+        assert_eq!(significantly_different(&b, &a), Some(true));
+        assert_eq!(significantly_different(&a, &c), Some(false));
+    }
+
+    #[test]
+    fn welch_t_test_needs_two_samples() {
+        let a = Samples::new(&[10.0], OutlierPolicy::None);
+        let b = Samples::new(&[20.0, 21.0], OutlierPolicy::None);
+        assert_eq!(significantly_different(&a, &b), None);
+        assert_eq!(significantly_different(&b, &a), None);
+    }
+
+    #[test]
+    fn welch_t_test_with_zero_variance() {
+        let a = Samples::new(&[5.0, 5.0, 5.0], OutlierPolicy::None);
+        let b = Samples::new(&[6.0, 6.0], OutlierPolicy::None);
+        assert_eq!(significantly_different(&a, &a.clone()), Some(false));
+        assert_eq!(significantly_different(&a, &b), Some(true));
+        // Only one side having variance still gives a finite number of degrees of freedom
+        let c = Samples::new(&[5.0, 5.1, 4.9], OutlierPolicy::None);
+        assert_eq!(significantly_different(&a, &c), Some(false));
+    }
+
+    #[test]
+    fn outlier_policy_names() {
+        for name in OUTLIER_POLICIES {
+            assert_eq!(name.parse::<OutlierPolicy>().unwrap().to_string(), name);
+        }
+        assert!("mean".parse::<OutlierPolicy>().is_err());
+    }
+}
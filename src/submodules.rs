@@ -0,0 +1,156 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::str::FromStr;
+
+use crate::allocators::AllocatorSpec;
+
+pub const SUBMODULE_POLICIES: [&str; 3] = ["offline", "update", "remote"];
+
+/// What the platform may do to the sources of the allocators before building them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubmodulePolicy {
+    /// Never touch the network or the sources. Sources that are missing or not at their pinned commit are an error
+    Offline,
+    /// Check out the pinned commit of sources that are missing or moved, fetching it if needed
+    Update,
+    /// Move the sources to the newest commit of their upstream branch
+    Remote
+}
+
+impl FromStr for SubmodulePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "offline" => Ok(SubmodulePolicy::Offline),
+            "update" => Ok(SubmodulePolicy::Update),
+            "remote" => Ok(SubmodulePolicy::Remote),
+            _ => Err(format!("{} is not a submodule policy", s))
+        }
+    }
+}
+
+/// The sources of an allocator compared to the commit that the repository pins them to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SourceState {
+    /// The sources are checked out at their pinned commit
+    Pinned(String),
+    /// The sources are not a submodule of the repository, so there is no commit to verify
+    Unpinned,
+    Missing,
+    Moved {
+        pinned: String,
+        current: Option<String>
+    }
+}
+
+/// Something wrong with the sources of an allocator, and the command that fixes it
+#[derive(Debug, Clone)]
+pub struct SourceProblem {
+    pub dir: PathBuf,
+    pub message: String,
+    pub fix: String
+}
+
+/// Runs a command and returns the first line of its output, if it succeeded
+fn first_line(command: &mut Command) -> Option<String> {
+    let output = command.output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8_lossy(&output.stdout).lines().next().map(|line| line.trim().to_string())
+}
+
+/// Whether a submodule directory has had its sources checked out
+pub fn is_checked_out(dir: &Path) -> bool {
+    std::fs::read_dir(dir)
+        .map(|mut entries| entries.any(|e| e.is_ok_and(|e| e.file_name() != ".git")))
+        .unwrap_or(false)
+}
+
+/// The commit that the repository pins a submodule to, from its index
+pub fn pinned_commit(dir: &Path) -> Option<String> {
+    // Submodules are listed as `160000 <commit> 0\t<path>`
+    let line = first_line(Command::new("git").args(["ls-files", "--stage", "--"]).arg(dir))?;
+    let mut fields = line.split_whitespace();
+    match (fields.next(), fields.next()) {
+        (Some("160000"), Some(commit)) => Some(commit.to_string()),
+        _ => None
+    }
+}
+
+/// Compares the commit that the sources in a directory are checked out at with their pinned commit
+pub fn source_state(dir: &Path) -> SourceState {
+    if !is_checked_out(dir) {
+        return SourceState::Missing;
+    }
+    let pinned = match pinned_commit(dir) {
+        Some(pinned) => pinned,
+        None => return SourceState::Unpinned
+    };
+    // Without its own repository, git would find the commit of the enclosing one
+    let current = if dir.join(".git").exists() {
+        first_line(Command::new("git").arg("-C").arg(dir).args(["rev-parse", "HEAD"]))
+    } else {
+        None
+    };
+    if current.as_deref() == Some(pinned.as_str()) {
+        SourceState::Pinned(pinned)
+    } else {
+        SourceState::Moved { pinned, current }
+    }
+}
+
+/// The command that checks out the pinned commit of the sources in a directory
+pub fn fix_command(dir: &Path) -> String {
+    format!("git submodule update --init -- {}", dir.display())
+}
+
+/// Finds the allocators whose sources are missing, or, if `require_pinned`, not at their pinned commit
+pub fn source_problems(allocators: &[&AllocatorSpec], require_pinned: bool) -> Vec<SourceProblem> {
+    let mut problems: Vec<SourceProblem> = vec![];
+    for allocator in allocators {
+        let dir = match allocator.source_dir() {
+            Some(dir) if !problems.iter().any(|p| p.dir == dir) => dir,
+            _ => continue
+        };
+        let mut fix = fix_command(dir);
+        let message = match source_state(dir) {
+            SourceState::Missing => {
+                if pinned_commit(dir).is_none() {
+                    fix = format!("put the sources of {} in {:?}, or change its source_dir in the registry", allocator.name(), dir);
+                }
+                format!("The sources of {} are not checked out in {:?}", allocator.name(), dir)
+            },
+            SourceState::Moved { pinned, current } if require_pinned => format!(
+                "The sources of {} are checked out at {} instead of their pinned commit {}",
+                allocator.name(),
+                current.as_deref().unwrap_or("an unknown commit"),
+                pinned
+            ),
+            _ => continue
+        };
+        problems.push(SourceProblem {
+            dir: dir.to_path_buf(),
+            message,
+            fix
+        });
+    }
+    problems
+}
+
+/// Checks out the submodules in the given directories, at their pinned commits, or at the newest commit
+/// of their upstream branch if `remote`
+pub fn update_submodules<P: AsRef<Path>>(dirs: &[P], remote: bool) -> Result<(), String> {
+    let mut command = Command::new("git");
+    command.args(["submodule", "update", "--init"]);
+    if remote {
+        command.arg("--remote");
+    }
+    command.arg("--").args(dirs.iter().map(|dir| dir.as_ref()));
+    let status = command.status().map_err(|e| format!("Could not run git: {}", e))?;
+    if !status.success() {
+        return Err(format!("`git submodule update` failed with {}", status));
+    }
+    Ok(())
+}
@@ -0,0 +1,155 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::str::FromStr;
+
+use crate::allocators::{AllocatorError, AllocatorSpec};
+
+/// Where the worktrees of allocator revisions are checked out
+pub const WORKTREE_DIR: &str = "./allocators/worktrees";
+
+/// Another version of an allocator to test, given as `[name=]allocator@rev-or-path`
+#[derive(Debug, Clone)]
+pub struct VariantSpec {
+    pub name: Option<String>,
+    pub allocator: String,
+    /// A git revision of the allocator's repository, or the path of a local checkout
+    pub source: String
+}
+
+impl FromStr for VariantSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, rest) = match s.split_once('=') {
+            Some((name, rest)) => (Some(name.trim().to_string()), rest),
+            None => (None, s)
+        };
+        let (allocator, source) = rest.split_once('@')
+            .ok_or_else(|| format!("{} is not a variant, expected [name=]allocator@rev-or-path", s))?;
+        if allocator.is_empty() || source.is_empty() || name.as_ref().is_some_and(|n| n.is_empty()) {
+            return Err(format!("{} is not a variant, expected [name=]allocator@rev-or-path", s));
+        }
+        Ok(Self {
+            name,
+            allocator: allocator.trim().to_string(),
+            source: source.trim().to_string()
+        })
+    }
+}
+
+/// Replaces the characters that can't be part of a library name
+fn sanitize(label: &str) -> String {
+    label.chars().map(|c| if c.is_ascii_alphanumeric() || c == '_' || c == '.' { c } else { '_' }).collect()
+}
+
+/// Runs git in a directory and returns the first line of its output
+fn git(dir: &Path, args: &[&str]) -> Result<String, AllocatorError> {
+    let output = Command::new("git").arg("-C").arg(dir).args(args).output()?;
+    if !output.status.success() {
+        return Err(AllocatorError::Revision(format!(
+            "`git {}` failed in {:?}: {}",
+            args.join(" "),
+            dir,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).lines().next().unwrap_or("").trim().to_string())
+}
+
+/// Finds the commit that a revision of the allocator's repository refers to
+pub fn resolve_commit(allocator: &AllocatorSpec, rev: &str) -> Result<String, AllocatorError> {
+    let source_dir = allocator.source_dir()
+        .ok_or_else(|| AllocatorError::Revision(format!("{} has no sources to take revisions from", allocator.name())))?;
+    git(source_dir, &["rev-parse", "--verify", "--quiet", &format!("{}^{{commit}}", rev)])
+        .map_err(|_| AllocatorError::Revision(format!("{} is not a revision of {}", rev, allocator.name())))
+}
+
+/// The commits after `good` up to and including `bad`, oldest first, following first parents only
+pub fn commit_range(allocator: &AllocatorSpec, good: &str, bad: &str) -> Result<Vec<String>, AllocatorError> {
+    let source_dir = allocator.source_dir()
+        .ok_or_else(|| AllocatorError::Revision(format!("{} has no sources to take revisions from", allocator.name())))?;
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(source_dir)
+        .args(["rev-list", "--first-parent", "--reverse", &format!("{}..{}", good, bad)])
+        .output()?;
+    if !output.status.success() {
+        return Err(AllocatorError::Revision(format!("Could not list the commits from {} to {}", good, bad)));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).lines().map(|line| line.trim().to_string()).collect())
+}
+
+/// The abbreviated hash and subject of a commit
+pub fn commit_summary(allocator: &AllocatorSpec, commit: &str) -> String {
+    allocator.source_dir()
+        .and_then(|dir| git(dir, &["log", "-1", "--format=%h %s", commit]).ok())
+        .unwrap_or_else(|| commit.to_string())
+}
+
+/// Checks out a commit of the allocator's repository into its own worktree, reusing it if it already exists
+pub fn checkout(allocator: &AllocatorSpec, commit: &str) -> Result<PathBuf, AllocatorError> {
+    let source_dir = allocator.source_dir()
+        .ok_or_else(|| AllocatorError::Revision(format!("{} has no sources to take revisions from", allocator.name())))?;
+    let worktree = PathBuf::from(WORKTREE_DIR).join(format!("{}-{}", allocator.name(), commit));
+    if worktree.exists() {
+        if git(&worktree, &["rev-parse", "HEAD"])? != commit {
+            git(&worktree, &["checkout", "--quiet", "--detach", commit])?;
+        }
+        return Ok(worktree);
+    }
+
+    std::fs::create_dir_all(WORKTREE_DIR)?;
+    let path = std::fs::canonicalize(WORKTREE_DIR)?.join(worktree.file_name().unwrap());
+    git(source_dir, &["worktree", "add", "--quiet", "--detach", path.to_str().unwrap(), commit])?;
+    Ok(worktree)
+}
+
+impl VariantSpec {
+
+    /// Creates the allocator of this variant, checking out its revision if it isn't a local path
+    ///
+    /// Variants are named `allocator@rev` or `allocator@<directory name>` unless they are given a name.
+    pub fn resolve(&self, allocator: &AllocatorSpec) -> Result<AllocatorSpec, AllocatorError> {
+        let path = Path::new(&self.source);
+        if path.is_dir() {
+            let label = path.canonicalize()?
+                .file_name()
+                .map_or_else(|| String::from("root"), |n| n.to_string_lossy().to_string());
+            let name = self.name.clone().unwrap_or_else(|| format!("{}@{}", allocator.name(), label));
+            let library = sanitize(&name);
+            return Ok(allocator.with_source(&name, path.to_path_buf(), &library, None));
+        }
+
+        let commit = resolve_commit(allocator, &self.source)?;
+        let worktree = checkout(allocator, &commit)?;
+        let name = self.name.clone().unwrap_or_else(|| format!("{}@{}", allocator.name(), self.source));
+        let library = format!("{}-{}", allocator.lib_name().unwrap_or(allocator.name()), &commit[..12]);
+        Ok(allocator.with_source(&name, worktree, &library, Some(commit)))
+    }
+}
+
+/// The worktrees checked out for revisions of the allocator
+pub fn worktrees(allocator: &AllocatorSpec) -> Vec<PathBuf> {
+    let prefix = format!("{}-", allocator.name());
+    let mut worktrees: Vec<PathBuf> = std::fs::read_dir(WORKTREE_DIR)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            // Worktrees are named <allocator>-<commit>
+            name.strip_prefix(&prefix).is_some_and(|commit| commit.len() == 40 && commit.chars().all(|c| c.is_ascii_hexdigit()))
+        })
+        .map(|entry| entry.path())
+        .collect();
+    worktrees.sort();
+    worktrees
+}
+
+/// Makes the repository of the allocator forget its worktrees that were removed
+pub fn prune_worktrees(allocator: &AllocatorSpec) {
+    if let Some(dir) = allocator.source_dir().filter(|dir| dir.exists()) {
+        // Sources that aren't git repositories have no worktrees
+        let _ = git(dir, &["worktree", "prune"]);
+    }
+}